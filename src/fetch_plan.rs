@@ -0,0 +1,171 @@
+//! Logic to determine which dependencies are going to be downloaded when cooking.
+use anyhow::Context;
+use std::path::Path;
+
+/// A package, pulled from a registry (or a git repository), that is listed in a lockfile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistryPackage {
+    pub name: String,
+    pub version: String,
+}
+
+/// List all packages in `lock_file` that are not local to the project, i.e. the ones pulled from
+/// a registry or a git repository, sorted by name and version.
+///
+/// Local crates are recognised by their lack of `source`, rather than by their masked version:
+/// a dependency can legitimately be at version `0.0.1`.
+pub fn external_packages(lock_file: &str) -> Result<Vec<RegistryPackage>, anyhow::Error> {
+    let lock_file: toml::Value = toml::from_str(lock_file).context("Failed to parse Cargo.lock")?;
+    let packages = lock_file
+        .get("package")
+        .and_then(|packages| packages.as_array())
+        .map(|packages| packages.as_slice())
+        .unwrap_or_default();
+    let mut external = vec![];
+    for package in packages
+        .iter()
+        .filter(|package| package.get("source").is_some())
+    {
+        let name = package.get("name").and_then(|name| name.as_str());
+        let version = package.get("version").and_then(|version| version.as_str());
+        if let (Some(name), Some(version)) = (name, version) {
+            external.push(RegistryPackage {
+                name: name.to_string(),
+                version: version.to_string(),
+            });
+        }
+    }
+    external.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+    external.dedup();
+    Ok(external)
+}
+
+/// A change to the external dependencies of a project, see [`dependency_changes`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DependencyChange {
+    Added(RegistryPackage),
+    Removed(RegistryPackage),
+    /// The project depended on a single version of `name` and still does, a different one.
+    Updated {
+        name: String,
+        from: String,
+        to: String,
+    },
+}
+
+impl std::fmt::Display for DependencyChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DependencyChange::Added(package) => write!(f, "+ {} {}", package.name, package.version),
+            DependencyChange::Removed(package) => {
+                write!(f, "- {} {}", package.name, package.version)
+            }
+            DependencyChange::Updated { name, from, to } => {
+                write!(f, "~ {} {} -> {}", name, from, to)
+            }
+        }
+    }
+}
+
+/// Compare the external packages (see [`external_packages`]) of two lock files, sorted by name.
+/// A missing lock file is treated as an empty one.
+pub fn dependency_changes(
+    previous_lock_file: Option<&str>,
+    current_lock_file: Option<&str>,
+) -> Result<Vec<DependencyChange>, anyhow::Error> {
+    let previous = previous_lock_file
+        .map(external_packages)
+        .transpose()?
+        .unwrap_or_default();
+    let current = current_lock_file
+        .map(external_packages)
+        .transpose()?
+        .unwrap_or_default();
+    let removed: Vec<&RegistryPackage> = previous
+        .iter()
+        .filter(|package| !current.contains(package))
+        .collect();
+    let added: Vec<&RegistryPackage> = current
+        .iter()
+        .filter(|package| !previous.contains(package))
+        .collect();
+    let names: std::collections::BTreeSet<&str> = removed
+        .iter()
+        .chain(added.iter())
+        .map(|package| package.name.as_str())
+        .collect();
+
+    let mut changes = vec![];
+    for name in names {
+        let removed: Vec<_> = removed.iter().filter(|p| p.name == name).collect();
+        let added: Vec<_> = added.iter().filter(|p| p.name == name).collect();
+        if let ([from], [to]) = (removed.as_slice(), added.as_slice()) {
+            changes.push(DependencyChange::Updated {
+                name: name.to_string(),
+                from: from.version.clone(),
+                to: to.version.clone(),
+            });
+            continue;
+        }
+        changes.extend(
+            removed
+                .into_iter()
+                .map(|package| DependencyChange::Removed((**package).clone())),
+        );
+        changes.extend(
+            added
+                .into_iter()
+                .map(|package| DependencyChange::Added((**package).clone())),
+        );
+    }
+    Ok(changes)
+}
+
+/// List all registry packages in `lock_file` that can't be found in the registry cache of
+/// `cargo_home` (i.e. `$CARGO_HOME/registry/cache`): `cargo` will have to download them.
+///
+/// Packages pulled from git repositories or local paths are ignored.
+pub fn missing_from_registry_cache(
+    lock_file: &str,
+    cargo_home: &Path,
+) -> Result<Vec<RegistryPackage>, anyhow::Error> {
+    let lock_file: toml::Value = toml::from_str(lock_file).context("Failed to parse Cargo.lock")?;
+    let packages = lock_file
+        .get("package")
+        .and_then(|packages| packages.as_array())
+        .map(|packages| packages.as_slice())
+        .unwrap_or_default();
+    // There is a cache directory per registry index.
+    let cache_directories: Vec<_> = match std::fs::read_dir(cargo_home.join("registry/cache")) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            .collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+        Err(e) => return Err(anyhow::Error::from(e).context("Failed to read the registry cache.")),
+    };
+
+    let mut missing = vec![];
+    for package in packages {
+        let is_from_registry = package
+            .get("source")
+            .and_then(|source| source.as_str())
+            .is_some_and(|source| source.starts_with("registry+") || source.starts_with("sparse+"));
+        let name = package.get("name").and_then(|name| name.as_str());
+        let version = package.get("version").and_then(|version| version.as_str());
+        if let (true, Some(name), Some(version)) = (is_from_registry, name, version) {
+            let archive = format!("{}-{}.crate", name, version);
+            if !cache_directories
+                .iter()
+                .any(|directory| directory.join(&archive).is_file())
+            {
+                missing.push(RegistryPackage {
+                    name: name.to_string(),
+                    version: version.to_string(),
+                });
+            }
+        }
+    }
+    Ok(missing)
+}
@@ -1,7 +1,15 @@
+mod fetch_plan;
 mod recipe;
 mod skeleton;
+mod toolchain;
 
+pub use fetch_plan::{
+    dependency_changes, external_packages, missing_from_registry_cache, DependencyChange,
+    RegistryPackage,
+};
 pub use recipe::{
-    AllFeatures, CommandArg, CookArgs, DefaultFeatures, OptimisationProfile, Recipe, TargetArgs,
+    AllFeatures, CommandArg, CookArgs, DefaultFeatures, OptimisationProfile, PrepareArgs,
+    PrepareFilter, Recipe, TargetArgs,
 };
 pub use skeleton::*;
+pub use toolchain::{missing_targets, ToolchainRequirements};
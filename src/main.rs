@@ -1,13 +1,17 @@
 use anyhow::{anyhow, Context};
 use chef::{
-    AllFeatures, CommandArg, CookArgs, DefaultFeatures, OptimisationProfile, Recipe, TargetArgs,
+    dependency_changes, external_packages, AllFeatures, CommandArg, CookArgs, DefaultFeatures,
+    OptimisationProfile, PrepareArgs, Recipe, RustToolchainFile, Skeleton, TargetArgs, TargetKind,
+    DEFAULT_BUILD_INPUT_PATTERNS,
 };
 use clap::crate_version;
-use clap::Parser;
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser};
 use fs_err as fs;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 use std::io::IsTerminal;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// Cache the dependencies of your Rust project.
 #[derive(Parser)]
@@ -42,33 +46,131 @@ pub enum Command {
     ///
     /// `cargo chef prepare` emits a recipe file that can be later used via
     /// `cargo chef cook --recipe <recipe-path>.json`.
-    Prepare(Prepare),
+    Prepare(Box<Prepare>),
     /// Re-hydrate the minimum project skeleton identified by `cargo chef prepare` and build
     /// it to cache dependencies.
-    Cook(Cook),
+    Cook(Box<Cook>),
 }
 
 #[derive(Parser)]
 pub struct Prepare {
     /// The filepath used to save the computed recipe.
+    /// The recipe is compressed with `gzip` if the filepath ends in `.gz` (e.g. `recipe.json.gz`),
+    /// which requires `cargo-chef` to be built with the `gzip` feature.
     ///
-    /// It defaults to "recipe.json".
-    #[arg(long, default_value = "recipe.json")]
+    /// It defaults to "recipe.json", unless `CARGO_CHEF_RECIPE_PATH` is set.
+    #[arg(long, env = "CARGO_CHEF_RECIPE_PATH", default_value = "recipe.json")]
     recipe_path: PathBuf,
 
     /// When --bin is specified, `cargo-chef` will ignore all members of the workspace
     /// that are not necessary to successfully compile the specific binary.
     #[arg(long)]
     bin: Option<String>,
+
+    /// Path to the `Cargo.toml` of the project to prepare, if it's not the one in the current
+    /// directory. The recipe is rooted at the workspace it belongs to.
+    #[arg(long)]
+    manifest_path: Option<PathBuf>,
+
+    /// Root the recipe in the specified directory instead of the root of the workspace detected
+    /// by `cargo`, e.g. to match the layout of a monorepo in the image. `Cargo.lock`,
+    /// `.cargo/config.toml` and the toolchain file are looked up in this directory.
+    #[arg(long, value_name = "PATH")]
+    workspace_root: Option<PathBuf>,
+
+    /// Leave the specified binary out of the recipe: `cook` does not create a dummy for it,
+    /// hence it's not built. The flag can be passed multiple times.
+    #[arg(long, value_name = "NAME")]
+    exclude_bin: Vec<String>,
+
+    /// Print a human-readable summary of the derived skeleton (manifests, targets, lockfile)
+    /// to stderr instead of saving the recipe. Useful to include in bug reports.
+    #[arg(long)]
+    print_skeleton: bool,
+
+    /// Compare the derived recipe with the one at the specified path and print the changes to
+    /// the external dependencies to stdout, instead of saving the recipe. One line per change:
+    /// `+ <name> <version>` (added), `- <name> <version>` (removed) or
+    /// `~ <name> <old-version> -> <new-version>` (updated).
+    #[arg(long, value_name = "PATH", conflicts_with = "print_skeleton")]
+    compare_to: Option<PathBuf>,
+
+    /// Save the output of `cargo metadata` to the specified path.
+    /// Useful to include in bug reports when `prepare` does not pick the expected members.
+    #[arg(long)]
+    dump_cargo_metadata: Option<PathBuf>,
+
+    /// Strip the manifest fields that do not influence what gets compiled (`description`,
+    /// `authors`, `keywords`, `categories`, `readme` and `[badges]`), to avoid invalidating
+    /// the recipe when they are edited. `[package.metadata]` is preserved.
+    /// Opt-in: a build script might read them via `CARGO_PKG_*` environment variables.
+    #[arg(long)]
+    minify: bool,
+
+    /// Capture the inputs of code generation build scripts into the recipe.
+    /// For crates with `tonic-build`, `prost-build`, `protobuf-codegen` or `protoc-rust`
+    /// among their build dependencies, `prepare` captures the `proto` and `protos` directories
+    /// as well as all `.proto` files next to the crate manifest.
+    #[arg(long)]
+    capture_build_inputs: bool,
+
+    /// Override the files captured by `--capture-build-inputs`, as glob patterns relative to
+    /// the crate manifest (e.g. `/schemas/**/*.proto`). The flag can be passed multiple times.
+    /// It implies `--capture-build-inputs`.
+    #[arg(long)]
+    build_input: Option<Vec<String>>,
+
+    /// Forward an extra argument to `cargo metadata` (e.g. `--metadata-arg=--filter-platform`
+    /// `--metadata-arg=x86_64-unknown-linux-gnu`). The flag can be passed multiple times.
+    /// `--no-deps` and `--format-version` are always set by `cargo-chef` and can't be overridden.
+    #[arg(long, allow_hyphen_values = true)]
+    metadata_arg: Vec<String>,
+
+    /// Pretty-print the recipe, to make changes easier to review.
+    /// The recipe is compact by default.
+    #[arg(long)]
+    pretty: bool,
+
+    /// Capture the specified configuration file of a `cargo` sub-command (e.g. `clippy.toml`),
+    /// relative to the project root, if it exists. The flag can be passed multiple times.
+    ///
+    /// By default `clippy.toml`, `.clippy.toml`, `rustfmt.toml`, `.rustfmt.toml` and `deny.toml`
+    /// are captured: passing this flag replaces the default list.
+    #[arg(long, conflicts_with = "no_tool_config_files")]
+    tool_config_file: Option<Vec<String>>,
+
+    /// Do not capture any configuration file of `cargo` sub-commands.
+    #[arg(long)]
+    no_tool_config_files: bool,
+
+    /// Also capture the independent projects nested in the project directory, i.e. the ones
+    /// with their own `Cargo.lock` that are not members of the workspace (e.g. a `tools`
+    /// project). Their dependencies can be cooked via `--manifest-path`.
+    #[arg(long)]
+    nested_projects: bool,
 }
 
 #[derive(Parser)]
+#[command(after_help = "Examples:
+  cargo chef cook --release --recipe-path recipe.json
+  cargo chef cook --check --then-build --features postgres
+  cargo chef cook --release --target musl --explain")]
 pub struct Cook {
     /// The filepath `cook` should be reading the recipe from.
+    /// Recipes compressed with `gzip` are decompressed transparently, if `cargo-chef` is built
+    /// with the `gzip` feature.
     ///
-    /// It defaults to "recipe.json".
-    #[arg(long, default_value = "recipe.json")]
+    /// It defaults to "recipe.json", unless `CARGO_CHEF_RECIPE_PATH` is set.
+    #[arg(long, env = "CARGO_CHEF_RECIPE_PATH", default_value = "recipe.json")]
     recipe_path: PathBuf,
+    /// Derive the recipe from the project in the specified directory, in memory, instead of
+    /// reading it from `--recipe-path`. Useful to warm up a fresh target directory without
+    /// going through `prepare`.
+    ///
+    /// The skeleton is re-hydrated in the current directory: it must not be the project's.
+    /// It cannot be combined with an explicit `--recipe-path`.
+    #[arg(long)]
+    prepare_from: Option<PathBuf>,
     /// Build artifacts with the specified profile.
     #[arg(long)]
     profile: Option<String>,
@@ -81,7 +183,15 @@ pub struct Cook {
     /// Run `cargo clippy` instead of `cargo build`. Primarily useful for speeding up your CI pipeline. Requires clippy to be installed.
     #[arg(long)]
     clippy: bool,
+    /// Run `cargo build` after `cargo check` (or `cargo clippy`), to warm up the caches of
+    /// both commands with a single cook.
+    #[arg(long)]
+    then_build: bool,
     /// Build for the target triple. The flag can be passed multiple times to cook for multiple targets.
+    ///
+    /// Full target triples are forwarded as they are, while the following aliases are expanded
+    /// using the architecture of the host: `musl` (`<arch>-unknown-linux-musl`), `gnu`
+    /// (`<arch>-unknown-linux-gnu`) and `darwin` (`<arch>-apple-darwin`).
     #[arg(long)]
     target: Option<Vec<String>>,
     /// Directory for all generated artifacts.
@@ -96,6 +206,11 @@ pub struct Cook {
     /// Space or comma separated list of features to activate.
     #[arg(long, value_delimiter = ',')]
     features: Option<Vec<String>>,
+    /// Comma separated list of features to build the dependencies with.
+    /// The flag can be passed multiple times: dependencies are built once per feature set,
+    /// on top of the features activated via `--features`.
+    #[arg(long)]
+    feature_set: Option<Vec<String>>,
     /// Unstable feature to activate (only available on the nightly channel).
     #[arg(short = 'Z')]
     unstable_features: Option<Vec<String>>,
@@ -108,6 +223,9 @@ pub struct Cook {
     /// Build all examples
     #[arg(long)]
     examples: bool,
+    /// Build only the specified example. This can be specified with multiple examples.
+    #[arg(long)]
+    example: Option<Vec<String>>,
     /// Build all targets.
     /// This is equivalent to specifying `--tests --benches --examples`.
     #[arg(long)]
@@ -121,24 +239,67 @@ pub struct Cook {
     /// Build all members in the workspace.
     #[arg(long)]
     workspace: bool,
+    /// Only build the external dependencies, skipping the (dummy) workspace members.
+    ///
+    /// `cook` selects, via `--package <name>@<version>`, the dependencies that members declare
+    /// under `[dependencies]`: `cargo` still resolves their features against the whole
+    /// workspace. Optional, platform-specific, build and dev dependencies are not selected
+    /// (`cargo` rejects them), they only get built if a selected dependency needs them.
+    /// The recipe must include a `Cargo.lock`.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "package", "workspace", "bin", "bins", "features", "feature_set",
+            "no_default_features", "all_features", "tests", "benches", "examples",
+            "all_targets", "example",
+        ]
+    )]
+    deps_only: bool,
     /// Build offline.
     #[arg(long)]
     offline: bool,
     /// Require Cargo.lock is up to date
     #[arg(long)]
     locked: bool,
-    /// Use verbose output
-    #[arg(long, short = 'v')]
-    verbose: bool,
+    /// Use verbose output (-vv very verbose/build.rs output)
+    #[arg(long, short = 'v', action = ArgAction::Count)]
+    verbose: u8,
     /// Require Cargo.lock and cache are up to date
     #[arg(long)]
     frozen: bool,
+    /// Delete the `Cargo.lock` restored from the recipe before building, letting `cargo`
+    /// resolve the dependencies again.
+    ///
+    /// This is an escape hatch for lock files that `cargo` considers out of date once the
+    /// versions of the local crates have been masked. The dependencies are re-resolved on
+    /// every cook: they might not match the ones of the project's `Cargo.lock`, in which case
+    /// the cached dependencies are not reused when building the project.
+    #[arg(long, conflicts_with_all = ["locked", "frozen"])]
+    regenerate_lock: bool,
+    /// Number of parallel jobs, defaults to # of CPUs.
+    #[arg(long, short = 'j')]
+    jobs: Option<usize>,
+    /// Set `--jobs` to the number of available CPUs minus one, to leave some headroom in
+    /// constrained containers. An explicit `--jobs` takes precedence.
+    #[arg(long)]
+    auto_jobs: bool,
     /// Report build timings.
     #[arg(long)]
     timings: bool,
+    /// Copy the report generated by `--timings` to the specified directory once the build is
+    /// done, to make it easier to extract from a Docker layer. It implies `--timings`.
+    #[arg(long)]
+    timings_dir: Option<PathBuf>,
     /// Cook using `#[no_std]` configuration  (does not affect `proc-macro` crates)
+    ///
+    /// Crates can override it by setting `no-std = true|false` in their
+    /// `[package.metadata.cargo-chef]` table.
     #[arg(long)]
     no_std: bool,
+    /// Leave the `#[panic_handler]` out of the `#[no_std]` dummies, for projects relying on the
+    /// one provided by a dependency (e.g. `panic-halt`) instead.
+    #[arg(long, requires = "no_std")]
+    no_std_no_panic_handler: bool,
     /// Build only the specified binary. This can be specified with multiple binaries.
     #[arg(long)]
     bin: Option<Vec<String>>,
@@ -154,162 +315,760 @@ pub struct Cook {
     /// projects that rely on a custom build system (i.e. not `cargo`).
     #[clap(long)]
     no_build: bool,
+    /// Install the targets and components listed in `rust-toolchain.toml` (via `rustup`)
+    /// before building. If `rustup` is not available, a warning is printed and the
+    /// installation is skipped.
+    #[arg(long)]
+    install_toolchain: bool,
+    /// Install the targets passed via `--target` (via `rustup`) if they are missing from the
+    /// active toolchain. Without this flag, a hint is printed for each missing target.
+    #[arg(long)]
+    install_targets: bool,
+    /// Do nothing if the dependencies have already been cooked, in the same target directory,
+    /// using the same recipe and the same options.
+    #[arg(long)]
+    skip_if_unchanged: bool,
+    /// Override the crate type(s) of a library in the workspace, e.g. `--crate-type
+    /// my-crate=cdylib,rlib`. The flag can be passed multiple times, once per library.
+    #[arg(long, value_name = "PACKAGE=CRATE-TYPES")]
+    crate_type: Option<Vec<String>>,
+    /// Add a feature to the `default` feature of a package in the recipe before building, e.g.
+    /// `--enable-feature my-crate=postgres`, to cache the dependencies it activates.
+    /// The flag can be passed multiple times.
+    #[arg(long, value_name = "PACKAGE=FEATURE")]
+    enable_feature: Option<Vec<String>>,
+    /// Comma separated list of features to add to the `default` feature of every package in
+    /// the recipe that declares them, e.g. `--workspace-features postgres`.
+    ///
+    /// Unlike `--workspace --features`, where `cargo` only enables the features for the members
+    /// declaring them, it makes sure the dependencies the features activate are cooked for the
+    /// whole workspace. Packages that don't declare a feature are left untouched.
+    #[arg(long, value_delimiter = ',')]
+    workspace_features: Option<Vec<String>>,
+    /// Override a configuration value, either inline (`--config 'build.jobs=4'`) or by layering
+    /// an additional configuration file (`--config extra.toml`). It is forwarded verbatim to
+    /// `cargo`. The flag can be passed multiple times.
+    ///
+    /// Network settings are often needed on flaky networks, e.g. `--config net.retry=5` or
+    /// `--config net.git-fetch-with-cli=true` to use the proxy settings of `git`.
+    #[arg(long, value_name = "KEY=VALUE|PATH")]
+    config: Option<Vec<String>>,
+    /// Make `cargo` more resilient to network failures while fetching dependencies, by
+    /// forwarding `--config net.retry=3 --config net.git-fetch-with-cli=true` (the latter
+    /// requires `git` to be installed). Values passed via `--config` take precedence.
+    #[arg(long)]
+    robust_network: bool,
+    /// Fail if any compiled dummy library is still in the target directory after the cleanup.
+    /// A self-check against artifacts of dummy crates leaking into the build of the project.
+    #[arg(long)]
+    verify_cleanup: bool,
+    /// Do not remove the compiled dummies from the target directory, e.g. if you only want to
+    /// warm up the registry cache and throw the target directory away.
+    ///
+    /// If you build the project on top of the target directory, you'll rely on the
+    /// fingerprinting of `cargo` to detect that the dummy crates must be rebuilt.
+    #[arg(long, conflicts_with = "verify_cleanup")]
+    no_cleanup: bool,
+    /// Write a JSON summary of the cook to the specified path, including the `RUSTFLAGS` and
+    /// `CARGO_ENCODED_RUSTFLAGS` the dependencies were built with: the project must be built
+    /// with the same flags to benefit from the cache.
+    #[arg(long, value_name = "PATH")]
+    json_summary: Option<PathBuf>,
+    /// Append the `cargo` commands executed to build the dependencies to the specified file,
+    /// one per line. The environment is not recorded.
+    #[arg(long, value_name = "PATH")]
+    command_log: Option<PathBuf>,
+    /// Print the `cargo` commands before running them, explaining where each of their flags
+    /// comes from.
+    #[arg(long)]
+    explain: bool,
+    /// Before building, list the dependencies that are not in the registry cache of
+    /// `CARGO_HOME`, i.e. the ones that are going to be downloaded. Useful to diagnose network
+    /// issues.
+    #[arg(long)]
+    report_fetch_plan: bool,
+    /// Print the external dependencies (name and version) listed in the lockfile of the recipe,
+    /// i.e. the crates that `cook` compiles, and exit without building anything.
+    #[arg(long)]
+    list_deps: bool,
+    /// Retry a failed build up to the specified number of times, with an exponential backoff
+    /// starting at one second. Useful when fetching dependencies fails intermittently.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    retry: u32,
+}
+
+fn recipe_path_from_command_line(matches: &ArgMatches) -> bool {
+    matches
+        .subcommand_matches("chef")
+        .and_then(|chef| chef.subcommand_matches("cook"))
+        .map(|cook| cook.value_source("recipe_path") == Some(ValueSource::CommandLine))
+        .unwrap_or(false)
 }
 
 fn _main() -> Result<(), anyhow::Error> {
     let current_directory = std::env::current_dir().unwrap();
 
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
     // "Unwrapping" the actual command.
     let command = match cli.command {
         CargoInvocation::Chef { command } => command,
     };
 
     match command {
-        Command::Cook(Cook {
-            recipe_path,
+        Command::Cook(cook) => {
+            // Listing the dependencies doesn't touch the filesystem.
+            if std::io::stdout().is_terminal() && !cook.list_deps {
+                eprintln!("WARNING stdout appears to be a terminal.");
+                eprintln!(
+                    "cargo-chef is not meant to be run in an interactive environment \
+                and will overwrite some existing files (namely any `lib.rs`, `main.rs` and \
+                `Cargo.toml` it finds)."
+                );
+                eprintln!();
+                eprint!("To continue anyway, type `yes`: ");
+
+                let mut answer = String::with_capacity(3);
+                std::io::stdin()
+                    .read_line(&mut answer)
+                    .context("Failed to read from stdin")?;
+
+                if "yes" != answer.trim() {
+                    std::process::exit(1);
+                }
+            }
+
+            let recipe_path = cook.recipe_path.clone();
+            let prepare_from = cook.prepare_from.clone();
+            let list_deps = cook.list_deps;
+            // `CARGO_CHEF_RECIPE_PATH` may be set globally (e.g. in a base image): only an
+            // explicit `--recipe-path` clashes with `--prepare-from`.
+            if prepare_from.is_some() && recipe_path_from_command_line(&matches) {
+                Err(anyhow!(
+                    "`--prepare-from` cannot be used together with `--recipe-path`."
+                ))?
+            }
+            let args = CookArgs::try_from(*cook)?;
+            let recipe = match prepare_from {
+                Some(project_directory) => {
+                    let project_directory = project_directory
+                        .canonicalize()
+                        .context("Failed to resolve the path of the project to prepare.")?;
+                    if project_directory == current_directory.canonicalize()? {
+                        Err(anyhow!("`--prepare-from` would overwrite the source files of the project with dummy ones. Please run `cook` from a different (empty) directory."))?
+                    }
+                    Recipe::prepare(project_directory, None).context("Failed to compute recipe")?
+                }
+                None => {
+                    let serialized = read_recipe(&recipe_path)
+                        .context("Failed to read recipe from the specified path.")?;
+                    serde_json::from_slice(&serialized).context("Failed to deserialize recipe.")?
+                }
+            };
+            if list_deps {
+                let lock_file = recipe.skeleton.lock_file.as_ref().ok_or_else(|| {
+                    anyhow!(
+                        "The recipe has no lockfile: the dependencies to be compiled are unknown."
+                    )
+                })?;
+                for package in external_packages(lock_file)? {
+                    println!("{} {}", package.name, package.version);
+                }
+                return Ok(());
+            }
+            recipe.cook(args).context("Failed to cook recipe.")?;
+        }
+        Command::Prepare(prepare) => {
+            let Prepare {
+                recipe_path,
+                bin,
+                manifest_path,
+                workspace_root,
+                exclude_bin,
+                print_skeleton,
+                compare_to,
+                dump_cargo_metadata,
+                minify,
+                capture_build_inputs,
+                build_input,
+                metadata_arg,
+                pretty,
+                tool_config_file,
+                no_tool_config_files,
+                nested_projects,
+            } = *prepare;
+            let build_inputs = match build_input {
+                Some(patterns) => Some(patterns),
+                None if capture_build_inputs => Some(
+                    DEFAULT_BUILD_INPUT_PATTERNS
+                        .iter()
+                        .map(|pattern| pattern.to_string())
+                        .collect(),
+                ),
+                None => None,
+            };
+            let workspace_root = workspace_root
+                .map(|workspace_root| {
+                    workspace_root
+                        .canonicalize()
+                        .context("Failed to resolve the path passed via `--workspace-root`.")
+                })
+                .transpose()?;
+            let args = PrepareArgs {
+                member: bin,
+                dump_cargo_metadata,
+                minify,
+                build_inputs,
+                metadata_args: metadata_arg,
+                tool_config_files: if no_tool_config_files {
+                    Some(vec![])
+                } else {
+                    tool_config_file
+                },
+                nested_projects,
+                excluded_bins: exclude_bin,
+                workspace_root,
+            };
+            let base_path = match manifest_path {
+                Some(manifest_path) => {
+                    if manifest_path.file_name() != Some("Cargo.toml".as_ref()) {
+                        Err(anyhow!(
+                            "`--manifest-path` must point to a `Cargo.toml` file, got `{}`.",
+                            manifest_path.display()
+                        ))?
+                    }
+                    let manifest_path = manifest_path
+                        .canonicalize()
+                        .context("Failed to resolve the path passed via `--manifest-path`.")?;
+                    manifest_path
+                        .parent()
+                        .expect("A manifest path always has a parent directory")
+                        .to_path_buf()
+                }
+                None => current_directory,
+            };
+            let recipe =
+                Recipe::prepare_with_args(base_path, args).context("Failed to compute recipe")?;
+            if print_skeleton {
+                eprint_skeleton(&recipe.skeleton);
+                return Ok(());
+            }
+            if let Some(compare_to) = compare_to {
+                let previous: Recipe = serde_json::from_slice(
+                    &read_recipe(&compare_to)
+                        .context("Failed to read the recipe passed via `--compare-to`.")?,
+                )
+                .context("Failed to deserialize the recipe passed via `--compare-to`.")?;
+                let changes = dependency_changes(
+                    previous.skeleton.lock_file.as_deref(),
+                    recipe.skeleton.lock_file.as_deref(),
+                )?;
+                if changes.is_empty() {
+                    eprintln!("The external dependencies are unchanged.");
+                }
+                for change in changes {
+                    println!("{}", change);
+                }
+                return Ok(());
+            }
+            let serialized = if pretty {
+                serde_json::to_string_pretty(&recipe)
+            } else {
+                serde_json::to_string(&recipe)
+            }
+            .context("Failed to serialize recipe.")?;
+            write_recipe(&recipe_path, serialized.into_bytes())
+                .context("Failed to save recipe to 'recipe.json'")?;
+        }
+    }
+    Ok(())
+}
+
+impl TryFrom<Cook> for CookArgs {
+    type Error = anyhow::Error;
+
+    /// Resolve and validate the command-line arguments of `cook`.
+    fn try_from(cook: Cook) -> Result<Self, Self::Error> {
+        let Cook {
+            recipe_path: _,
+            prepare_from: _,
             profile,
             release,
             check,
             clippy,
+            then_build,
             target,
             no_default_features,
             all_features,
             features,
+            feature_set,
             unstable_features,
             target_dir,
             benches,
             tests,
             examples,
             all_targets,
+            example,
             manifest_path,
             package,
             workspace,
+            deps_only,
             offline,
             frozen,
             locked,
+            regenerate_lock,
             verbose,
+            jobs,
+            auto_jobs,
             timings,
+            timings_dir,
             no_std,
+            no_std_no_panic_handler,
             bin,
             zigbuild,
             bins,
             no_build,
-        }) => {
-            if std::io::stdout().is_terminal() {
-                eprintln!("WARNING stdout appears to be a terminal.");
-                eprintln!(
-                    "cargo-chef is not meant to be run in an interactive environment \
-                and will overwrite some existing files (namely any `lib.rs`, `main.rs` and \
-                `Cargo.toml` it finds)."
-                );
-                eprintln!();
-                eprint!("To continue anyway, type `yes`: ");
+            install_toolchain,
+            install_targets,
+            skip_if_unchanged,
+            crate_type,
+            enable_feature,
+            workspace_features,
+            config,
+            robust_network,
+            verify_cleanup,
+            report_fetch_plan,
+            list_deps: _,
+            retry,
+            no_cleanup,
+            json_summary,
+            command_log,
+            explain,
+        } = cook;
+        // Just like `cargo`, we accept space-separated features on top of comma-separated ones.
+        let features: Option<HashSet<String>> = features.and_then(|features| {
+            let features: HashSet<String> = features
+                .iter()
+                .flat_map(|features| features.split_whitespace())
+                .map(|feature| feature.to_string())
+                .collect();
+            if features.is_empty() {
+                None
+            } else {
+                Some(features)
+            }
+        });
 
-                let mut answer = String::with_capacity(3);
-                std::io::stdin()
-                    .read_line(&mut answer)
-                    .context("Failed to read from stdin")?;
+        let feature_sets: Option<Vec<HashSet<String>>> = feature_set.map(|feature_sets| {
+            feature_sets
+                .iter()
+                .map(|feature_set| {
+                    feature_set
+                        .split(',')
+                        .filter(|feature| !feature.is_empty())
+                        .map(|feature| feature.to_string())
+                        .collect()
+                })
+                .collect()
+        });
 
-                if "yes" != answer.trim() {
-                    std::process::exit(1);
+        let crate_types: Option<HashMap<String, Vec<String>>> = crate_type
+            .map(|crate_types| {
+                crate_types
+                    .iter()
+                    .map(|crate_type| {
+                        let (package, crate_types) = crate_type.split_once('=').ok_or_else(|| {
+                            anyhow!("`--crate-type` expects values in the `<package>=<crate-types>` format, got `{}`", crate_type)
+                        })?;
+                        Ok((
+                            package.to_string(),
+                            crate_types.split(',').map(|t| t.to_string()).collect(),
+                        ))
+                    })
+                    .collect::<Result<_, anyhow::Error>>()
+            })
+            .transpose()?;
+
+        let enabled_features: Option<HashMap<String, Vec<String>>> = enable_feature
+            .map(|enable_feature| {
+                let mut enabled_features: HashMap<String, Vec<String>> = HashMap::new();
+                for value in enable_feature {
+                    let (package, feature) = value.split_once('=').ok_or_else(|| {
+                        anyhow!("`--enable-feature` expects values in the `<package>=<feature>` format, got `{}`", value)
+                    })?;
+                    enabled_features
+                        .entry(package.to_string())
+                        .or_default()
+                        .push(feature.to_string());
+                }
+                Ok::<_, anyhow::Error>(enabled_features)
+            })
+            .transpose()?;
+
+        let target: Option<Vec<String>> =
+            target.map(|targets| targets.into_iter().map(expand_target_alias).collect());
+
+        if let Some(config) = &config {
+            // Just like `cargo`, we consider anything that is not a `KEY=VALUE` assignment
+            // to be the path to a configuration file.
+            for config in config.iter().filter(|config| !config.contains('=')) {
+                if !Path::new(config).is_file() {
+                    Err(anyhow!("`--config {}` is neither a `KEY=VALUE` assignment nor the path to an existing configuration file.", config))?
                 }
             }
+        }
 
-            let features: Option<HashSet<String>> = features.and_then(|features| {
-                if features.is_empty() {
+        // `cargo` merges `--config` values from left to right: the explicit ones come last.
+        let config = if robust_network {
+            let mut robust_config: Vec<String> = ROBUST_NETWORK_CONFIG
+                .iter()
+                .map(|config| config.to_string())
+                .collect();
+            robust_config.extend(config.unwrap_or_default());
+            Some(robust_config)
+        } else {
+            config
+        };
+
+        let unstable_features: Option<HashSet<String>> =
+            unstable_features.and_then(|unstable_features| {
+                if unstable_features.is_empty() {
                     None
                 } else {
-                    Some(features.into_iter().collect())
+                    Some(unstable_features.into_iter().collect())
                 }
             });
 
-            let unstable_features: Option<HashSet<String>> =
-                unstable_features.and_then(|unstable_features| {
-                    if unstable_features.is_empty() {
-                        None
-                    } else {
-                        Some(unstable_features.into_iter().collect())
-                    }
-                });
-
-            let profile = match (release, profile) {
-                (false, None) =>  OptimisationProfile::Debug,
-                (false, Some(profile)) if profile == "dev" => OptimisationProfile::Debug,
-                (true, None) => OptimisationProfile::Release,
-                (false, Some(profile)) if profile == "release" => OptimisationProfile::Release,
-                (false, Some(custom_profile)) => OptimisationProfile::Other(custom_profile),
-                (true, Some(_)) => Err(anyhow!("You specified both --release and --profile arguments. Please remove one of them, or both"))?
-            };
-            let command = match (check, clippy, zigbuild, no_build) {
-                (true, false, false, false) => CommandArg::Check,
-                (false, true, false, false) => CommandArg::Clippy,
-                (false, false, true, false) => CommandArg::Zigbuild,
-                (false, false, false, true) => CommandArg::NoBuild,
-                (false, false, false, false) => CommandArg::Build,
-                _ => Err(anyhow!("Only one (or none) of the  `clippy`, `check`, `zigbuild`, and `no-build` arguments are allowed. Please remove some of them, or all"))?,
-            };
+        if let Some(unstable_features) = &unstable_features {
+            if unstable_features.contains("host-config") {
+                if !unstable_features.contains("target-applies-to-host") {
+                    Err(anyhow!("`-Z host-config` requires `-Z target-applies-to-host` to be enabled as well."))?
+                }
+                if target.is_none() {
+                    Err(anyhow!("`-Z host-config` only has an effect when cross-compiling. Please specify a target via `--target`."))?
+                }
+            }
+        }
 
-            let default_features = if no_default_features {
-                DefaultFeatures::Disabled
-            } else {
-                DefaultFeatures::Enabled
-            };
+        let jobs = match (jobs, auto_jobs) {
+            (Some(jobs), _) => Some(jobs),
+            (None, true) => Some(auto_job_count(
+                std::thread::available_parallelism().map_or(1, usize::from),
+            )),
+            (None, false) => None,
+        };
 
-            let all_features = if all_features {
-                AllFeatures::Enabled
-            } else {
-                AllFeatures::Disabled
-            };
+        let profile = match (release, profile) {
+            (false, None) =>  OptimisationProfile::Debug,
+            (false, Some(profile)) if profile == "dev" => OptimisationProfile::Debug,
+            (true, None) => OptimisationProfile::Release,
+            (false, Some(profile)) if profile == "release" => OptimisationProfile::Release,
+            (false, Some(custom_profile)) => OptimisationProfile::Other(custom_profile),
+            (true, Some(_)) => Err(anyhow!("You specified both --release and --profile arguments. Please remove one of them, or both"))?
+        };
+        let command = match (check, clippy, zigbuild, no_build) {
+            (true, false, false, false) => CommandArg::Check,
+            (false, true, false, false) => CommandArg::Clippy,
+            (false, false, true, false) => CommandArg::Zigbuild,
+            (false, false, false, true) => CommandArg::NoBuild,
+            (false, false, false, false) => CommandArg::Build,
+            _ => Err(anyhow!("Only one (or none) of the  `clippy`, `check`, `zigbuild`, and `no-build` arguments are allowed. Please remove some of them, or all"))?,
+        };
+        if then_build && !matches!(command, CommandArg::Check | CommandArg::Clippy) {
+            Err(anyhow!(
+                "`--then-build` can only be used together with `--check` or `--clippy`."
+            ))?
+        }
+
+        let default_features = if no_default_features {
+            DefaultFeatures::Disabled
+        } else {
+            DefaultFeatures::Enabled
+        };
+
+        let all_features = if all_features {
+            AllFeatures::Enabled
+        } else {
+            AllFeatures::Disabled
+        };
+
+        let target_args = TargetArgs {
+            benches,
+            tests,
+            examples,
+            all_targets,
+            example,
+        };
+        Ok(CookArgs {
+            profile,
+            command,
+            then_build,
+            default_features,
+            all_features,
+            features,
+            feature_sets,
+            unstable_features,
+            target,
+            target_dir,
+            target_args,
+            manifest_path,
+            package,
+            workspace,
+            deps_only,
+            offline,
+            timings: timings || timings_dir.is_some(),
+            no_std,
+            no_panic_handler: no_std_no_panic_handler,
+            bin,
+            locked,
+            frozen,
+            regenerate_lock,
+            verbose,
+            jobs,
+            bins,
+            no_build,
+            install_toolchain,
+            install_targets,
+            skip_if_unchanged,
+            crate_types,
+            enabled_features,
+            workspace_features,
+            config,
+            timings_dir,
+            verify_cleanup,
+            report_fetch_plan,
+            retries: retry,
+            skip_cleanup: no_cleanup,
+            json_summary,
+            command_log,
+            explain,
+        })
+    }
+}
+
+/// The number of jobs picked by `cook --auto-jobs`: all the available CPUs but one, so that
+/// `cargo` does not starve the rest of the container. At least one job is always used.
+fn auto_job_count(available_parallelism: usize) -> usize {
+    available_parallelism.saturating_sub(1).max(1)
+}
+
+/// The `--config` values forwarded to `cargo` by `cook --robust-network`.
+const ROBUST_NETWORK_CONFIG: &[&str] = &["net.retry=3", "net.git-fetch-with-cli=true"];
+
+/// The first two bytes of any gzip-compressed file.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
 
-            let serialized = fs::read_to_string(recipe_path)
-                .context("Failed to read recipe from the specified path.")?;
-            let recipe: Recipe =
-                serde_json::from_str(&serialized).context("Failed to deserialize recipe.")?;
-            let target_args = TargetArgs {
-                benches,
-                tests,
-                examples,
-                all_targets,
+/// Read a recipe, transparently decompressing it if it was compressed with `gzip`.
+fn read_recipe(path: &Path) -> Result<Vec<u8>, anyhow::Error> {
+    let contents = fs::read(path)?;
+    if contents.starts_with(&GZIP_MAGIC) {
+        return decompress(&contents);
+    }
+    Ok(contents)
+}
+
+/// Write a recipe, compressing it with `gzip` if `path` ends in `.gz`.
+fn write_recipe(path: &Path, contents: Vec<u8>) -> Result<(), anyhow::Error> {
+    let contents = if path.extension().is_some_and(|extension| extension == "gz") {
+        compress(&contents)?
+    } else {
+        contents
+    };
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+#[cfg(feature = "gzip")]
+fn compress(contents: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+    use std::io::Write;
+
+    let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+    encoder.write_all(contents)?;
+    Ok(encoder.finish()?)
+}
+
+#[cfg(feature = "gzip")]
+fn decompress(contents: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+    use std::io::Read;
+
+    let mut decompressed = vec![];
+    flate2::read::GzDecoder::new(contents)
+        .read_to_end(&mut decompressed)
+        .context("Failed to decompress the recipe.")?;
+    Ok(decompressed)
+}
+
+#[cfg(not(feature = "gzip"))]
+fn compress(_contents: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+    Err(anyhow!(GZIP_DISABLED))
+}
+
+#[cfg(not(feature = "gzip"))]
+fn decompress(_contents: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+    Err(anyhow!(GZIP_DISABLED))
+}
+
+#[cfg(not(feature = "gzip"))]
+const GZIP_DISABLED: &str = "Recipes compressed with `gzip` require `cargo-chef` to be built with \
+    the `gzip` feature, e.g. `cargo install cargo-chef --locked --features gzip`.";
+
+/// Short aliases for common target triples, keyed by alias.
+/// The architecture of the host is prepended to the expanded value.
+const TARGET_ALIASES: &[(&str, &str)] = &[
+    ("musl", "unknown-linux-musl"),
+    ("gnu", "unknown-linux-gnu"),
+    ("darwin", "apple-darwin"),
+];
+
+/// Expand `target` into a full target triple if it's one of [`TARGET_ALIASES`], return it
+/// unchanged otherwise.
+fn expand_target_alias(target: String) -> String {
+    match TARGET_ALIASES.iter().find(|(alias, _)| *alias == target) {
+        Some((_, suffix)) => format!("{}-{}", std::env::consts::ARCH, suffix),
+        None => target,
+    }
+}
+
+fn eprint_skeleton(skeleton: &Skeleton) {
+    eprintln!("manifests:");
+    for manifest in &skeleton.manifests {
+        eprintln!("  {}", manifest.relative_path.display());
+        for target in &manifest.targets {
+            let kind = match target.kind() {
+                TargetKind::Lib {
+                    is_proc_macro: true,
+                } => "proc-macro",
+                TargetKind::Lib {
+                    is_proc_macro: false,
+                } => "lib",
+                TargetKind::Bin => "bin",
+                TargetKind::Test => "test",
+                TargetKind::Bench => "bench",
+                TargetKind::Example => "example",
+                TargetKind::BuildScript => "build-script",
             };
-            recipe
-                .cook(CookArgs {
-                    profile,
-                    command,
-                    default_features,
-                    all_features,
-                    features,
-                    unstable_features,
-                    target,
-                    target_dir,
-                    target_args,
-                    manifest_path,
-                    package,
-                    workspace,
-                    offline,
-                    timings,
-                    no_std,
-                    bin,
-                    locked,
-                    frozen,
-                    verbose,
-                    bins,
-                    no_build,
-                })
-                .context("Failed to cook recipe.")?;
-        }
-        Command::Prepare(Prepare { recipe_path, bin }) => {
-            let recipe =
-                Recipe::prepare(current_directory, bin).context("Failed to compute recipe")?;
-            let serialized =
-                serde_json::to_string(&recipe).context("Failed to serialize recipe.")?;
-            fs::write(recipe_path, serialized).context("Failed to save recipe to 'recipe.json'")?;
+            eprintln!(
+                "    {} `{}` at {}",
+                kind,
+                target.name(),
+                target.path().display()
+            );
         }
     }
-    Ok(())
+    let presence = |file: &Option<String>| if file.is_some() { "present" } else { "absent" };
+    eprintln!("lock file: {}", presence(&skeleton.lock_file));
+    eprintln!("config file: {}", presence(&skeleton.config_file));
+    let rust_toolchain_file = match &skeleton.rust_toolchain_file {
+        Some((RustToolchainFile::Bare, _)) => "rust-toolchain",
+        Some((RustToolchainFile::Toml, _)) => "rust-toolchain.toml",
+        None => "absent",
+    };
+    eprintln!("rust toolchain file: {}", rust_toolchain_file);
 }
 
 fn main() -> Result<(), anyhow::Error> {
     env_logger::init();
     _main()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cook_args(args: &[&str]) -> Result<CookArgs, anyhow::Error> {
+        let cli = Cli::try_parse_from(["cargo", "chef", "cook"].iter().chain(args))?;
+        match cli.command {
+            CargoInvocation::Chef {
+                command: Command::Cook(cook),
+            } => CookArgs::try_from(*cook),
+            _ => unreachable!(),
+        }
+    }
+
+    fn cook_error(args: &[&str]) -> String {
+        match cook_args(args) {
+            Ok(_) => panic!("{:?} should have been rejected", args),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn release_and_profile_are_mutually_exclusive() {
+        let error = cook_error(&["--release", "--profile", "custom"]);
+        assert!(error.contains("both --release and --profile arguments"));
+    }
+
+    #[test]
+    fn only_one_command_flag_is_allowed() {
+        for flags in [
+            ["--check", "--clippy"],
+            ["--clippy", "--zigbuild"],
+            ["--check", "--no-build"],
+        ] {
+            let error = cook_error(&flags);
+            assert!(error.contains("Only one (or none) of the"));
+        }
+    }
+
+    #[test]
+    fn then_build_requires_check_or_clippy() {
+        for flags in [&["--then-build"][..], &["--zigbuild", "--then-build"]] {
+            let error = cook_error(flags);
+            assert!(error.contains("`--then-build` can only be used together with"));
+        }
+        assert!(cook_args(&["--clippy", "--then-build"]).unwrap().then_build);
+    }
+
+    #[test]
+    fn features_can_be_separated_by_commas_or_spaces() {
+        let expected = Some(HashSet::from([
+            "a".to_string(),
+            "b".to_string(),
+            "c".to_string(),
+        ]));
+        for flags in [
+            &["--features", "a,b,c"][..],
+            &["--features", "a b  c"],
+            &["--features", "a, b", "--features", " c "],
+        ] {
+            assert_eq!(cook_args(flags).unwrap().features, expected);
+        }
+        assert_eq!(cook_args(&["--features", " "]).unwrap().features, None);
+    }
+
+    #[test]
+    fn auto_jobs_leaves_one_cpu_free() {
+        assert_eq!(auto_job_count(8), 7);
+        assert_eq!(auto_job_count(2), 1);
+        assert_eq!(auto_job_count(1), 1);
+
+        assert!(cook_args(&["--auto-jobs"]).unwrap().jobs.is_some());
+        let args = cook_args(&["--auto-jobs", "--jobs", "16"]).unwrap();
+        assert_eq!(args.jobs, Some(16));
+        assert_eq!(cook_args(&[]).unwrap().jobs, None);
+    }
+
+    #[test]
+    fn verbose_can_be_repeated() {
+        assert_eq!(cook_args(&[]).unwrap().verbose, 0);
+        assert_eq!(cook_args(&["-v"]).unwrap().verbose, 1);
+        assert_eq!(cook_args(&["-vv"]).unwrap().verbose, 2);
+        assert_eq!(cook_args(&["--verbose", "-v"]).unwrap().verbose, 2);
+    }
+
+    #[test]
+    fn profile_and_command_are_resolved() {
+        let args = cook_args(&["--profile", "release", "--check"]).unwrap();
+        assert_eq!(args.profile, OptimisationProfile::Release);
+        assert!(matches!(args.command, CommandArg::Check));
+
+        let args = cook_args(&["--feature-set", "a,,b", "--feature-set", "c"]).unwrap();
+        assert_eq!(
+            args.feature_sets,
+            Some(vec![
+                HashSet::from(["a".to_string(), "b".to_string()]),
+                HashSet::from(["c".to_string()]),
+            ])
+        );
+    }
+}
@@ -1,13 +1,66 @@
-use crate::Skeleton;
+use crate::toolchain::check_targets;
+use crate::{
+    missing_from_registry_cache, MinimumProjectArgs, Skeleton, TargetKind, ToolchainRequirements,
+};
 use anyhow::Context;
+use fs_err as fs;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
-use std::path::PathBuf;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::{Duration, Instant};
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
 pub struct Recipe {
     pub skeleton: Skeleton,
+    /// The version of `cargo-chef` that generated the recipe.
+    /// It's empty for recipes generated before it was introduced.
+    #[serde(default)]
+    pub generator_version: String,
+    /// The filter used by `prepare` to prune the workspace, if any.
+    /// It's `None` for recipes generated before it was introduced.
+    #[serde(default)]
+    pub prepared_for: Option<PrepareFilter>,
+}
+
+/// How `prepare` narrowed down the members of the workspace.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PrepareFilter {
+    /// All members of the workspace are part of the recipe.
+    None,
+    /// Only the member providing this binary (`--bin`) is part of the recipe.
+    Bin(String),
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct PrepareArgs {
+    /// Ignore all workspace members that are not required to build this member.
+    pub member: Option<String>,
+    /// Save the output of `cargo metadata` to this path, for troubleshooting purposes.
+    pub dump_cargo_metadata: Option<PathBuf>,
+    /// Strip the manifest sections that have no influence on what gets compiled
+    /// (e.g. `description` or `[badges]`).
+    pub minify: bool,
+    /// Glob patterns of the files to capture for crates relying on code generation in their
+    /// build script (e.g. `tonic-build`). The capture is disabled if `None`.
+    pub build_inputs: Option<Vec<String>>,
+    /// Extra arguments forwarded to `cargo metadata` (e.g. `--filter-platform`).
+    pub metadata_args: Vec<String>,
+    /// Configuration files of `cargo` sub-commands to capture, relative to the project root.
+    /// [`DEFAULT_TOOL_CONFIG_FILES`](crate::DEFAULT_TOOL_CONFIG_FILES) are captured if `None`.
+    pub tool_config_files: Option<Vec<String>>,
+    /// Also capture the independent projects (i.e. with their own `Cargo.lock`) nested inside
+    /// the project directory, e.g. a `tools` workspace excluded from the main one.
+    pub nested_projects: bool,
+    /// Binaries to leave out of the skeleton: no dummy is created for them.
+    pub excluded_bins: Vec<String>,
+    /// Root the skeleton in this directory instead of the workspace root detected by
+    /// `cargo metadata`. The lock file, the `cargo` configuration and the toolchain file are
+    /// looked up in this directory as well. All manifests must be inside of it.
+    pub workspace_root: Option<PathBuf>,
 }
 
 pub struct TargetArgs {
@@ -15,6 +68,8 @@ pub struct TargetArgs {
     pub tests: bool,
     pub examples: bool,
     pub all_targets: bool,
+    /// Build only the specified examples.
+    pub example: Option<Vec<String>>,
 }
 
 pub enum CommandArg {
@@ -28,9 +83,13 @@ pub enum CommandArg {
 pub struct CookArgs {
     pub profile: OptimisationProfile,
     pub command: CommandArg,
+    /// Run `cargo build` after `command`, to warm up the caches of both. The compiled dummies
+    /// are cleaned up once, at the end: the artifacts of both commands are removed.
+    pub then_build: bool,
     pub default_features: DefaultFeatures,
     pub all_features: AllFeatures,
     pub features: Option<HashSet<String>>,
+    pub feature_sets: Option<Vec<HashSet<String>>>,
     pub unstable_features: Option<HashSet<String>>,
     pub target: Option<Vec<String>>,
     pub target_dir: Option<PathBuf>,
@@ -38,43 +97,455 @@ pub struct CookArgs {
     pub manifest_path: Option<PathBuf>,
     pub package: Option<Vec<String>>,
     pub workspace: bool,
+    /// Build the external dependencies of the workspace members, but not the members themselves.
+    pub deps_only: bool,
     pub offline: bool,
     pub locked: bool,
     pub frozen: bool,
-    pub verbose: bool,
+    /// Delete the restored `Cargo.lock`, to let `cargo` resolve the dependencies again.
+    pub regenerate_lock: bool,
+    /// How many times `--verbose` is forwarded to `cargo`.
+    pub verbose: u8,
+    /// Forwarded to `cargo` as `--jobs`.
+    pub jobs: Option<usize>,
     pub timings: bool,
     pub no_std: bool,
+    /// Leave the `#[panic_handler]` out of the `no_std` dummies.
+    pub no_panic_handler: bool,
     pub bin: Option<Vec<String>>,
     pub bins: bool,
     pub no_build: bool,
+    pub install_toolchain: bool,
+    /// Install the targets passed via `--target` (using `rustup`) if they are missing.
+    pub install_targets: bool,
+    pub skip_if_unchanged: bool,
+    pub crate_types: Option<HashMap<String, Vec<String>>>,
+    /// Features to add to the `default` feature of a package, keyed by package name.
+    pub enabled_features: Option<HashMap<String, Vec<String>>>,
+    /// Features to add to the `default` feature of every package declaring them.
+    pub workspace_features: Option<Vec<String>>,
+    pub config: Option<Vec<String>>,
+    /// Copy the report generated by `--timings` to this directory.
+    pub timings_dir: Option<PathBuf>,
+    /// Fail if compiled dummies are still around after the cleanup.
+    pub verify_cleanup: bool,
+    /// How many times a failed build is retried.
+    pub retries: u32,
+    /// Leave the compiled dummies in the target directory.
+    pub skip_cleanup: bool,
+    /// Print the dependencies that are missing from the local registry cache before building.
+    pub report_fetch_plan: bool,
+    /// Write a JSON summary of the cook (e.g. the `RUSTFLAGS` in use) to this path.
+    pub json_summary: Option<PathBuf>,
+    /// Append the `cargo` invocations to this file before running them.
+    pub command_log: Option<PathBuf>,
+    /// Explain, on `stderr`, how the `cargo` commands have been assembled.
+    pub explain: bool,
 }
 
 impl Recipe {
     pub fn prepare(base_path: PathBuf, member: Option<String>) -> Result<Self, anyhow::Error> {
-        let skeleton = Skeleton::derive(base_path, member)?;
-        Ok(Recipe { skeleton })
+        Self::prepare_with_args(
+            base_path,
+            PrepareArgs {
+                member,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Same as [`Recipe::prepare`], with all the knobs exposed by `cargo chef prepare`.
+    pub fn prepare_with_args(base_path: PathBuf, args: PrepareArgs) -> Result<Self, anyhow::Error> {
+        let skeleton = Skeleton::derive_with_args(base_path, &args)?;
+        let prepared_for = match args.member {
+            Some(member) => PrepareFilter::Bin(member),
+            None => PrepareFilter::None,
+        };
+        Ok(Recipe {
+            skeleton,
+            generator_version: GENERATOR_VERSION.to_string(),
+            prepared_for: Some(prepared_for),
+        })
     }
 
-    pub fn cook(&self, args: CookArgs) -> Result<(), anyhow::Error> {
+    pub fn cook(&self, mut args: CookArgs) -> Result<(), anyhow::Error> {
+        if self.generator_version != GENERATOR_VERSION {
+            let generator_version = if self.generator_version.is_empty() {
+                "an unknown version"
+            } else {
+                &self.generator_version
+            };
+            eprintln!(
+                "WARNING The recipe was generated by cargo-chef {}, but it is being cooked by \
+                cargo-chef {}. Make sure to use the same version in all stages of your build.",
+                generator_version, GENERATOR_VERSION
+            );
+        }
+        if let Some(PrepareFilter::Bin(bin)) = &self.prepared_for {
+            if args.workspace {
+                eprintln!(
+                    "WARNING The recipe was prepared with `--bin {}`: the other members of the \
+                    workspace were pruned, hence `--workspace` only builds the dependencies of `{}`.",
+                    bin, bin
+                );
+            }
+        }
+        let has_unqualified_features = args
+            .features
+            .iter()
+            .flatten()
+            .any(|feature| !feature.contains('/'));
+        if has_unqualified_features && args.package.is_none() && !args.workspace {
+            let default_members = self.skeleton.default_member_count()?;
+            if default_members > 1 {
+                eprintln!(
+                    "WARNING `cargo` builds the {} default members of the workspace: the features \
+                    passed via `--features` are only enabled for the members declaring them. \
+                    Use `<package>/<feature>` or `--package` to be explicit.",
+                    default_members
+                );
+            }
+        }
+        if let Some(examples) = &args.target_args.example {
+            for example in examples {
+                let is_known = self.skeleton.manifests.iter().any(|manifest| {
+                    manifest.targets.iter().any(|target| {
+                        target.kind() == &TargetKind::Example && target.name() == example
+                    })
+                });
+                if !is_known {
+                    anyhow::bail!("There is no example named `{}` in the recipe.", example);
+                }
+            }
+        }
+        if let Some(features) = &args.features {
+            self.skeleton.check_features(features)?;
+        }
+        if (args.locked || args.frozen) && !args.no_build && self.skeleton.lock_file.is_none() {
+            anyhow::bail!(
+                "`--{}` requires a `Cargo.lock`, but the recipe doesn't have one: the project had \
+                no `Cargo.lock` when `cargo chef prepare` was run. Generate it (e.g. via \
+                `cargo generate-lockfile`) and prepare the recipe again.",
+                if args.frozen { "frozen" } else { "locked" }
+            );
+        }
+        if !self.skeleton.has_packages() {
+            // `cargo` refuses to build a virtual manifest without members.
+            eprintln!("WARNING The recipe has no packages: there is nothing to build.");
+            args.command = CommandArg::NoBuild;
+            args.then_build = false;
+        } else if args.deps_only {
+            // Selecting the dependencies by name keeps `cargo` from compiling the dummy members,
+            // while it still resolves the features of the dependencies for the whole workspace.
+            let dependencies = self.skeleton.external_dependencies()?;
+            if dependencies.is_empty() {
+                eprintln!("WARNING The workspace has no external dependencies to build.");
+                args.command = CommandArg::NoBuild;
+                args.then_build = false;
+            } else {
+                args.package = Some(dependencies);
+            }
+        }
         let current_directory = std::env::current_dir()?;
-        self.skeleton
-            .build_minimum_project(&current_directory, args.no_std)?;
+        let mut skeleton = self.skeleton.clone();
+        if let Some(crate_types) = &args.crate_types {
+            for (package, crate_types) in crate_types {
+                skeleton.set_crate_type(package, crate_types)?;
+            }
+        }
+        if let Some(enabled_features) = &args.enabled_features {
+            for (package, features) in enabled_features {
+                skeleton.enable_features(package, features)?;
+            }
+        }
+        if let Some(workspace_features) = &args.workspace_features {
+            skeleton.enable_workspace_features(workspace_features)?;
+        }
+        if !args.no_build {
+            // A custom build system might compile all targets, we only trim them down for `cargo`.
+            skeleton.retain_targets(&args.target_args);
+        }
+        let cook_marker = if args.skip_if_unchanged && !args.no_build {
+            let target_dir =
+                skeleton.resolve_target_dir(&current_directory, args.target_dir.clone());
+            let marker_path = target_dir.join(COOK_MARKER);
+            let key = cook_marker_key(&args);
+            let fingerprint = cook_fingerprint(&skeleton, &args)?;
+            // Markers written by older versions of `cargo-chef` (a bare fingerprint) are ignored.
+            let mut fingerprints: BTreeMap<String, String> = fs::read_to_string(&marker_path)
+                .ok()
+                .and_then(|marker| serde_json::from_str(&marker).ok())
+                .unwrap_or_default();
+            if fingerprints.get(&key) == Some(&fingerprint) {
+                eprintln!("Dependencies have already been cooked for this recipe.");
+                return Ok(());
+            }
+            fingerprints.insert(key, fingerprint);
+            Some((marker_path, fingerprints))
+        } else {
+            None
+        };
+        timed("Rehydrating the skeleton", || {
+            skeleton.build_minimum_project_with_args(
+                &current_directory,
+                &MinimumProjectArgs {
+                    no_std: args.no_std,
+                    no_panic_handler: args.no_panic_handler,
+                },
+            )
+        })?;
+        if args.regenerate_lock {
+            let lock_file = current_directory.join("Cargo.lock");
+            if lock_file.exists() {
+                fs::remove_file(lock_file)?;
+            }
+        }
+        if args.install_toolchain {
+            if let Some((file_kind, contents)) = &skeleton.rust_toolchain_file {
+                ToolchainRequirements::parse(file_kind, contents)?
+                    .install(&current_directory)
+                    .context("Failed to install the requirements of the rust toolchain file.")?;
+            }
+        }
         if args.no_build {
             return Ok(());
         }
-        build_dependencies(&args);
-        self.skeleton
-            .remove_compiled_dummies(
-                current_directory,
+        for directory in skeleton.replacement_source_directories(&current_directory)? {
+            if !directory.is_dir() {
+                anyhow::bail!(
+                    "The `cargo` configuration of the recipe replaces a source with `{}`, which \
+                    does not exist. Vendored dependencies are not part of the recipe: copy them \
+                    in before running `cook`.",
+                    directory.display()
+                );
+            }
+        }
+        if let Some(target) = &args.target {
+            check_targets(target, &current_directory, args.install_targets)?;
+        }
+        if args.report_fetch_plan {
+            report_fetch_plan(&skeleton)?;
+        }
+        let rustflags = Rustflags::from_env();
+        let target_dir = skeleton.resolve_target_dir(&current_directory, args.target_dir.clone());
+        if let Some(configured_target_dir) = skeleton.configured_target_dir(&current_directory) {
+            if args.target_dir.is_some() && configured_target_dir != target_dir {
+                eprintln!(
+                    "WARNING `--target-dir` (or `CARGO_TARGET_DIR`) takes precedence over \
+                    `build.target-dir` (from `CARGO_BUILD_TARGET_DIR` or the `cargo` configuration \
+                    of the recipe): the dependencies are going to be built in `{}` rather than \
+                    in `{}`.",
+                    target_dir.display(),
+                    configured_target_dir.display()
+                );
+            }
+        }
+        let rustflags_marker = target_dir.join(RUSTFLAGS_MARKER);
+        if let Some(previous) = fs::read_to_string(&rustflags_marker)
+            .ok()
+            .and_then(|previous| serde_json::from_str::<Rustflags>(&previous).ok())
+        {
+            if previous != rustflags {
+                eprintln!(
+                    "WARNING The target directory was last cooked with different `RUSTFLAGS`: \
+                    the dependencies are going to be rebuilt. Make sure to build your project \
+                    with the same `RUSTFLAGS` you cook with, or the cache will be invalidated."
+                );
+            }
+        }
+        let has_built = timed("Building the dependencies", || build_dependencies(&args))?;
+        if has_built {
+            // Record the flags the dependencies were built with, to help troubleshooting cache
+            // misses.
+            fs::create_dir_all(&target_dir)?;
+            fs::write(&rustflags_marker, serde_json::to_string(&rustflags)?)?;
+        }
+        if let Some(timings_dir) = &args.timings_dir {
+            copy_timings_report(&target_dir, timings_dir)
+                .context("Failed to copy the timings report.")?;
+        }
+        if !args.skip_cleanup {
+            timed("Cleaning up dummy artifacts", || {
+                skeleton.remove_compiled_dummies(
+                    &current_directory,
+                    args.profile.clone(),
+                    args.target.clone(),
+                    args.target_dir.clone(),
+                )
+            })
+            .context("Failed to clean up dummy compilation artifacts.")?;
+        }
+        if args.verify_cleanup {
+            let leftovers = skeleton.find_compiled_dummies(
+                &current_directory,
                 args.profile,
                 args.target,
                 args.target_dir,
-            )
-            .context("Failed to clean up dummy compilation artifacts.")?;
+            )?;
+            if !leftovers.is_empty() {
+                let leftovers: Vec<_> = leftovers
+                    .iter()
+                    .map(|path| format!("- {}", path.display()))
+                    .collect();
+                anyhow::bail!(
+                    "Some compiled dummies were not cleaned up, they might leak into the \
+                    build of your project:\n{}",
+                    leftovers.join("\n")
+                );
+            }
+        }
+        if let Some((marker_path, fingerprints)) = cook_marker {
+            if let Some(parent) = marker_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(marker_path, serde_json::to_string(&fingerprints)?)?;
+        }
+        if let Some(json_summary) = &args.json_summary {
+            let summary = CookSummary {
+                generator_version: GENERATOR_VERSION,
+                target_dir,
+                rustflags,
+            };
+            fs::write(json_summary, serde_json::to_string_pretty(&summary)?)
+                .context("Failed to write the summary of the cook.")?;
+        }
         Ok(())
     }
 }
 
+/// Recorded in the target directory by `cook`, next to the compiled dependencies.
+const RUSTFLAGS_MARKER: &str = ".cargo-chef-rustflags";
+
+/// The extra flags passed by `cargo` to `rustc`: a change invalidates all compiled dependencies.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+struct Rustflags {
+    /// The value of `RUSTFLAGS`.
+    rustflags: Option<String>,
+    /// The value of `CARGO_ENCODED_RUSTFLAGS`, which takes precedence over `RUSTFLAGS`.
+    encoded_rustflags: Option<String>,
+}
+
+impl Rustflags {
+    fn from_env() -> Self {
+        Self {
+            rustflags: std::env::var("RUSTFLAGS").ok(),
+            encoded_rustflags: std::env::var("CARGO_ENCODED_RUSTFLAGS").ok(),
+        }
+    }
+}
+
+/// The summary written by `cook` when `--json-summary` is specified.
+#[derive(Serialize)]
+struct CookSummary {
+    generator_version: &'static str,
+    target_dir: PathBuf,
+    rustflags: Rustflags,
+}
+
+/// A fingerprint of everything that determines the outcome of `cook`: the skeleton to be
+/// cooked, the `cargo` invocations used to build the dependencies and the flags `cargo` passes
+/// on to `rustc`.
+///
+/// The fingerprint is only guaranteed to be stable for a given `cargo-chef` binary.
+/// The artifacts of each profile and target triple are stored in a different directory, hence
+/// `cook` keeps track of the last fingerprint of every combination separately: switching from
+/// `debug` to `release` and back does not trigger a rebuild of the `debug` dependencies.
+fn cook_marker_key(args: &CookArgs) -> String {
+    let mut key = match &args.profile {
+        OptimisationProfile::Release => "release".to_string(),
+        OptimisationProfile::Debug => "debug".to_string(),
+        OptimisationProfile::Other(custom_profile) => custom_profile.clone(),
+    };
+    for target in args.target.iter().flatten() {
+        key.push(' ');
+        key.push_str(target);
+    }
+    key
+}
+
+fn cook_fingerprint(skeleton: &Skeleton, args: &CookArgs) -> Result<String, anyhow::Error> {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(skeleton)?.hash(&mut hasher);
+    args.no_std.hash(&mut hasher);
+    // Flags that change what is left in the target directory, or what gets compiled, without
+    // showing up in the `cargo` invocations.
+    args.no_panic_handler.hash(&mut hasher);
+    args.regenerate_lock.hash(&mut hasher);
+    args.skip_cleanup.hash(&mut hasher);
+    args.verify_cleanup.hash(&mut hasher);
+    Rustflags::from_env().hash(&mut hasher);
+    for command in cargo_commands(args) {
+        command.get_program().hash(&mut hasher);
+        for arg in command.get_args() {
+            arg.hash(&mut hasher);
+        }
+    }
+    Ok(format!("{:016x}", hasher.finish()))
+}
+
+/// Print the registry dependencies that `cargo` will have to download, i.e. the ones that are
+/// not in the registry cache of `CARGO_HOME`.
+fn report_fetch_plan(skeleton: &Skeleton) -> Result<(), anyhow::Error> {
+    let cargo_home = std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cargo")));
+    let (lock_file, cargo_home) = match (&skeleton.lock_file, cargo_home) {
+        (Some(lock_file), Some(cargo_home)) => (lock_file, cargo_home),
+        (None, _) => {
+            eprintln!(
+                "WARNING The recipe has no lockfile: the dependencies to be fetched are unknown."
+            );
+            return Ok(());
+        }
+        (_, None) => {
+            eprintln!(
+                "WARNING Failed to locate CARGO_HOME: the dependencies to be fetched are unknown."
+            );
+            return Ok(());
+        }
+    };
+    let missing = missing_from_registry_cache(lock_file, &cargo_home)?;
+    if missing.is_empty() {
+        eprintln!("All dependencies are in the registry cache, nothing will be fetched.");
+    } else {
+        eprintln!(
+            "{} dependencies are not in the registry cache and will be fetched:",
+            missing.len()
+        );
+        for package in missing {
+            eprintln!("  {} {}", package.name, package.version);
+        }
+    }
+    Ok(())
+}
+
+/// Copy the HTML reports generated by `cargo build --timings` out of the target directory.
+fn copy_timings_report(target_dir: &Path, destination: &Path) -> Result<(), anyhow::Error> {
+    fs::create_dir_all(destination)?;
+    for entry in fs::read_dir(target_dir.join("cargo-timings"))? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            fs::copy(entry.path(), destination.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Run `phase`, logging how long it took at the info level.
+fn timed<T>(phase: &str, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let output = f();
+    log::info!("{} took {:.2?}", phase, start.elapsed());
+    output
+}
+
+/// The version of `cargo-chef` in use, recorded in all generated recipes.
+const GENERATOR_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Name of the file, in the target directory, storing the fingerprint of the last
+/// successful `cook` invocation for each profile and set of target triples.
+const COOK_MARKER: &str = ".cargo-chef-cook";
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum OptimisationProfile {
     Release,
@@ -94,13 +565,143 @@ pub enum AllFeatures {
     Disabled,
 }
 
-fn build_dependencies(args: &CookArgs) {
+/// Run the `cargo` commands building the dependencies. Returns `false` if there was nothing
+/// to run (e.g. the recipe has no packages).
+fn build_dependencies(args: &CookArgs) -> Result<bool, anyhow::Error> {
+    let commands = cargo_commands(args);
+    let has_commands = !commands.is_empty();
+    for mut command in commands {
+        if let Some(command_log) = &args.command_log {
+            log_command(command_log, &command)
+                .with_context(|| format!("Failed to log to {}.", command_log.display()))?;
+        }
+        if args.explain {
+            eprintln!("{}", explain_command(args, &command));
+        }
+        execute_command(&mut command, args.retries);
+    }
+    Ok(has_commands)
+}
+
+/// `command` as it would be typed in a shell, without its environment.
+fn command_line(command: &Command) -> String {
+    let mut line = String::from("cargo");
+    for arg in command.get_args() {
+        line.push(' ');
+        line.push_str(&arg.to_string_lossy());
+    }
+    line
+}
+
+/// Append `command` to `path`, one line per invocation.
+/// Only the arguments are recorded: the environment might contain secrets.
+fn log_command(path: &Path, command: &Command) -> Result<(), anyhow::Error> {
+    use std::io::Write;
+
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(file, "{}", command_line(command))?;
+    Ok(())
+}
+
+/// A breakdown of why `command` looks the way it does, one line per flag, for `--explain`.
+fn explain_command(args: &CookArgs, command: &Command) -> String {
+    const FLAGS_WITH_VALUE: &[&str] = &[
+        "--profile",
+        "--features",
+        "-Z",
+        "--target",
+        "--config",
+        "--target-dir",
+        "--example",
+        "--manifest-path",
+        "--package",
+        "--bin",
+        "--jobs",
+    ];
+
+    let mut lines = vec![format!("Running `{}`:", command_line(command))];
+    let mut command_args = command
+        .get_args()
+        .map(|arg| arg.to_string_lossy().into_owned());
+    if let Some(subcommand) = command_args.next() {
+        let reason = match (subcommand.as_str(), &args.command) {
+            ("build", CommandArg::Build) => "the default command".to_string(),
+            ("build", _) => "`--then-build` was passed".to_string(),
+            (subcommand, _) => format!("`--{}` was passed", subcommand),
+        };
+        lines.push(format!("  {}: {}", subcommand, reason));
+    }
+    while let Some(flag) = command_args.next() {
+        let flag = if FLAGS_WITH_VALUE.contains(&flag.as_str()) {
+            format!("{} {}", flag, command_args.next().unwrap_or_default())
+        } else {
+            flag
+        };
+        let name = flag.split(' ').next().unwrap_or_default();
+        let reason = match name {
+            "--release" => "`--release` (or `--profile release`) was passed".to_string(),
+            "--features" if args.feature_sets.is_some() => {
+                "`--features` combined with one of the `--feature-set`s".to_string()
+            }
+            "--package" if args.deps_only => {
+                "an external dependency of the workspace, selected by `--deps-only`".to_string()
+            }
+            "--target" => "`--target` was passed, aliases are expanded to full triples".to_string(),
+            "--target-dir" => "`--target-dir` (or `CARGO_TARGET_DIR`) was set".to_string(),
+            "--config" => "`--config` (or `--robust-network`) was passed".to_string(),
+            "--jobs" => "`--jobs` (or `--auto-jobs`) was passed".to_string(),
+            "--timings" if args.timings_dir.is_some() => {
+                "`--timings-dir` implies `--timings`".to_string()
+            }
+            name => format!("`{}` was passed", name),
+        };
+        lines.push(format!("  {}: {}", flag, reason));
+    }
+    lines.join("\n")
+}
+
+/// The `cargo` invocations required to build the dependencies: one for every requested
+/// feature set, on top of the features that have been specified via `--features`.
+/// If `then_build` is set, the `cargo build` invocations follow the ones of the main command.
+fn cargo_commands(args: &CookArgs) -> Vec<Command> {
+    let mut command_args = vec![&args.command];
+    if args.then_build {
+        command_args.push(&CommandArg::Build);
+    }
+    command_args
+        .into_iter()
+        .flat_map(|command_arg| match &args.feature_sets {
+            None => cargo_command(args, command_arg, args.features.as_ref())
+                .into_iter()
+                .collect::<Vec<_>>(),
+            Some(feature_sets) => feature_sets
+                .iter()
+                .filter_map(|feature_set| {
+                    let mut features = args.features.clone().unwrap_or_default();
+                    features.extend(feature_set.iter().cloned());
+                    cargo_command(args, command_arg, Some(&features))
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+fn cargo_command(
+    args: &CookArgs,
+    command_arg: &CommandArg,
+    features: Option<&HashSet<String>>,
+) -> Option<Command> {
     let CookArgs {
         profile,
-        command: command_arg,
+        command: _command,
+        then_build: _then_build,
         default_features,
         all_features,
-        features,
+        features: _features,
+        feature_sets: _feature_sets,
         unstable_features,
         target,
         target_dir,
@@ -108,15 +709,34 @@ fn build_dependencies(args: &CookArgs) {
         manifest_path,
         package,
         workspace,
+        deps_only: _deps_only,
         offline,
         frozen,
         locked,
+        regenerate_lock: _regenerate_lock,
         verbose,
+        jobs,
         timings,
         bin,
         no_std: _no_std,
+        no_panic_handler: _no_panic_handler,
         bins,
         no_build: _no_build,
+        install_toolchain: _install_toolchain,
+        install_targets: _install_targets,
+        skip_if_unchanged: _skip_if_unchanged,
+        crate_types: _crate_types,
+        enabled_features: _enabled_features,
+        workspace_features: _workspace_features,
+        config,
+        timings_dir: _timings_dir,
+        verify_cleanup: _verify_cleanup,
+        retries: _retries,
+        skip_cleanup: _skip_cleanup,
+        report_fetch_plan: _report_fetch_plan,
+        json_summary: _json_summary,
+        command_log: _command_log,
+        explain: _explain,
     } = args;
     let cargo_path = std::env::var("CARGO").expect("The `CARGO` environment variable was not set. This is unexpected: it should always be provided by `cargo` when invoking a custom sub-command, allowing `cargo-chef` to correctly detect which toolchain should be used. Please file a bug.");
     let mut command = Command::new(cargo_path);
@@ -125,7 +745,7 @@ fn build_dependencies(args: &CookArgs) {
         CommandArg::Check => command.arg("check"),
         CommandArg::Clippy => command.arg("clippy"),
         CommandArg::Zigbuild => command.arg("zigbuild"),
-        CommandArg::NoBuild => return,
+        CommandArg::NoBuild => return None,
     };
     if profile == &OptimisationProfile::Release {
         command_with_args.arg("--release");
@@ -135,15 +755,18 @@ fn build_dependencies(args: &CookArgs) {
     if default_features == &DefaultFeatures::Disabled {
         command_with_args.arg("--no-default-features");
     }
-    if let Some(features) = features {
-        let feature_flag = features.iter().cloned().collect::<Vec<String>>().join(",");
-        command_with_args.arg("--features").arg(feature_flag);
+    if let Some(features) = features.filter(|features| !features.is_empty()) {
+        let mut features = features.iter().cloned().collect::<Vec<String>>();
+        features.sort();
+        command_with_args.arg("--features").arg(features.join(","));
     }
     if all_features == &AllFeatures::Enabled {
         command_with_args.arg("--all-features");
     }
     if let Some(unstable_features) = unstable_features {
-        for unstable_feature in unstable_features.iter().cloned() {
+        let mut unstable_features = unstable_features.iter().cloned().collect::<Vec<String>>();
+        unstable_features.sort();
+        for unstable_feature in unstable_features {
             command_with_args.arg("-Z").arg(unstable_feature);
         }
     }
@@ -152,6 +775,11 @@ fn build_dependencies(args: &CookArgs) {
             command_with_args.arg("--target").arg(target);
         }
     }
+    if let Some(config) = config {
+        for config in config {
+            command_with_args.arg("--config").arg(config);
+        }
+    }
     if let Some(target_dir) = target_dir {
         command_with_args.arg("--target-dir").arg(target_dir);
     }
@@ -167,6 +795,11 @@ fn build_dependencies(args: &CookArgs) {
     if target_args.all_targets {
         command_with_args.arg("--all-targets");
     }
+    if let Some(example) = &target_args.example {
+        for example in example {
+            command_with_args.arg("--example").arg(example);
+        }
+    }
     if let Some(manifest_path) = manifest_path {
         command_with_args.arg("--manifest-path").arg(manifest_path);
     }
@@ -192,9 +825,12 @@ fn build_dependencies(args: &CookArgs) {
     if *locked {
         command_with_args.arg("--locked");
     }
-    if *verbose {
+    for _ in 0..*verbose {
         command_with_args.arg("--verbose");
     }
+    if let Some(jobs) = jobs {
+        command_with_args.arg("--jobs").arg(jobs.to_string());
+    }
     if *timings {
         command_with_args.arg("--timings");
     }
@@ -202,18 +838,35 @@ fn build_dependencies(args: &CookArgs) {
         command_with_args.arg("--bins");
     }
 
-    execute_command(command_with_args);
+    Some(command)
 }
 
-fn execute_command(command: &mut Command) {
-    let mut child = command
-        .envs(std::env::vars())
-        .spawn()
-        .expect("Failed to execute process");
+/// Run `command`, running it again up to `retries` times if it fails.
+/// We can't tell transient failures (e.g. a network hiccup while fetching dependencies) apart
+/// from permanent ones without capturing the output of `cargo`, therefore all failures are retried.
+fn execute_command(command: &mut Command, retries: u32) {
+    let mut attempt = 0;
+    loop {
+        let mut child = command
+            .envs(std::env::vars())
+            .spawn()
+            .expect("Failed to execute process");
 
-    let exit_status = child.wait().expect("Failed to run command");
+        let exit_status = child.wait().expect("Failed to run command");
 
-    if !exit_status.success() {
+        if exit_status.success() {
+            return;
+        }
+        if attempt < retries {
+            attempt += 1;
+            let backoff = Duration::from_secs(1 << (attempt - 1).min(5));
+            eprintln!(
+                "WARNING The build failed ({}), retrying in {:?} (attempt {} of {}).",
+                exit_status, backoff, attempt, retries
+            );
+            std::thread::sleep(backoff);
+            continue;
+        }
         match exit_status.code() {
             Some(code) => panic!("Exited with status code: {}", code),
             None => panic!("Process terminated by signal"),
@@ -0,0 +1,34 @@
+use super::ParsedManifest;
+
+/// Fields of `[package]` (and `[workspace.package]`) that have no influence on what gets
+/// compiled: editing them should not invalidate the recipe.
+const NON_ESSENTIAL_PACKAGE_FIELDS: &[&str] =
+    &["description", "authors", "keywords", "categories", "readme"];
+
+/// Remove the sections that are irrelevant to dependency resolution from all manifests.
+/// `[package.metadata]` is left untouched: build scripts might rely on it.
+pub(super) fn strip_non_essential_sections(manifests: &mut [ParsedManifest]) {
+    for manifest in manifests.iter_mut() {
+        if let Some(contents) = manifest.contents.as_table_mut() {
+            contents.remove("badges");
+        }
+        if let Some(package) = manifest.contents.get_mut("package") {
+            strip_non_essential_package_fields(package);
+        }
+        if let Some(workspace_package) = manifest
+            .contents
+            .get_mut("workspace")
+            .and_then(|workspace| workspace.get_mut("package"))
+        {
+            strip_non_essential_package_fields(workspace_package);
+        }
+    }
+}
+
+fn strip_non_essential_package_fields(package: &mut toml::Value) {
+    if let Some(package) = package.as_table_mut() {
+        for field in NON_ESSENTIAL_PACKAGE_FIELDS {
+            package.remove(*field);
+        }
+    }
+}
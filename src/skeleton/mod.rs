@@ -1,9 +1,10 @@
+mod minify;
 mod read;
 mod target;
 mod version_masking;
 
-use crate::skeleton::target::{Target, TargetKind};
-use crate::OptimisationProfile;
+pub use crate::skeleton::target::{Target, TargetKind};
+use crate::{OptimisationProfile, PrepareArgs, TargetArgs};
 use anyhow::Context;
 use cargo_manifest::Product;
 use cargo_metadata::Metadata;
@@ -11,7 +12,8 @@ use fs_err as fs;
 use globwalk::GlobWalkerBuilder;
 use pathdiff::diff_paths;
 use serde::{Deserialize, Serialize};
-use std::path::{Path, PathBuf};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Component, Path, PathBuf};
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
 pub struct Skeleton {
@@ -19,8 +21,33 @@ pub struct Skeleton {
     pub config_file: Option<String>,
     pub lock_file: Option<String>,
     pub rust_toolchain_file: Option<(RustToolchainFile, String)>,
+    /// Files, other than manifests, that are required to build the dependencies
+    /// (e.g. the `.proto` files compiled by a build script).
+    #[serde(default)]
+    pub extra_files: Vec<ExtraFile>,
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ExtraFile {
+    /// Relative path with respect to the project root.
+    pub relative_path: PathBuf,
+    pub contents: String,
+}
+
+/// The files captured, for crates relying on code generation in their build script, when no
+/// custom patterns are provided. They are relative to the directory of the crate manifest.
+pub const DEFAULT_BUILD_INPUT_PATTERNS: &[&str] = &["/proto/**", "/protos/**", "/*.proto"];
+
+/// The configuration files of `cargo` sub-commands (e.g. `cargo clippy`) that are captured, if
+/// present at the root of the project, when no custom list is provided.
+pub const DEFAULT_TOOL_CONFIG_FILES: &[&str] = &[
+    "clippy.toml",
+    ".clippy.toml",
+    "rustfmt.toml",
+    ".rustfmt.toml",
+    "deny.toml",
+];
+
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
 pub enum RustToolchainFile {
     Bare,
@@ -35,6 +62,19 @@ pub struct Manifest {
     pub targets: Vec<Target>,
 }
 
+/// Options of [`Skeleton::build_minimum_project_with_args`].
+#[derive(Debug, Default, Clone)]
+pub struct MinimumProjectArgs {
+    /// Create `#![no_std]` dummies, unless a crate overrides it in its manifest.
+    pub no_std: bool,
+    /// Leave the `#[panic_handler]` out of the `no_std` dummies, for projects relying on the
+    /// one provided by a dependency (e.g. `panic-halt`).
+    pub no_panic_handler: bool,
+}
+
+/// The extensions of the library artifacts produced by `rustc`, across platforms and crate types.
+const LIBRARY_EXTENSIONS: &[&str] = &["rlib", "rmeta", "so", "dylib", "dll", "lib", "a", "wasm"];
+
 pub(in crate::skeleton) struct ParsedManifest {
     relative_path: PathBuf,
     contents: toml::Value,
@@ -43,27 +83,120 @@ pub(in crate::skeleton) struct ParsedManifest {
 
 impl Skeleton {
     /// Find all Cargo.toml files in `base_path` by traversing sub-directories recursively.
+    ///
+    /// If `base_path` belongs to a workspace, the skeleton is rooted at the workspace root.
     pub fn derive<P: AsRef<Path>>(
         base_path: P,
         member: Option<String>,
     ) -> Result<Self, anyhow::Error> {
-        let metadata = extract_cargo_metadata(base_path.as_ref())?;
+        Self::derive_with_args(
+            base_path,
+            &PrepareArgs {
+                member,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Same as [`Skeleton::derive`], with all the knobs exposed by `cargo chef prepare`.
+    pub fn derive_with_args<P: AsRef<Path>>(
+        base_path: P,
+        args: &PrepareArgs,
+    ) -> Result<Self, anyhow::Error> {
+        let metadata = extract_cargo_metadata(base_path.as_ref(), &args.metadata_args)?;
+        if let Some(path) = &args.dump_cargo_metadata {
+            fs::write(path, serde_json::to_string_pretty(&metadata)?)
+                .context("Failed to save the output of `cargo metadata`.")?;
+        }
+        // `cargo metadata` looks for the workspace root in all parent directories. We root the
+        // skeleton there, even if we have been invoked from the directory of a workspace member.
+        let base_path = match &args.workspace_root {
+            Some(workspace_root) => workspace_root.clone(),
+            None => metadata.workspace_root.clone().into_std_path_buf(),
+        };
 
         // Read relevant files from the filesystem
-        let config_file = read::config(&base_path)?;
+        let config_file = read::config(&base_path)?.map(normalize_line_endings);
         let mut manifests = read::manifests(&base_path, &metadata)?;
-        if let Some(member) = member {
-            ignore_all_members_except(&mut manifests, &metadata, member);
+        if args.workspace_root.is_some() {
+            let outside = manifests
+                .iter()
+                .find(|manifest| escapes_root(&manifest.relative_path));
+            if let Some(manifest) = outside {
+                anyhow::bail!(
+                    "The manifest at `{}` is not inside the root of the skeleton, `{}`.",
+                    base_path.join(&manifest.relative_path).display(),
+                    base_path.display()
+                );
+            }
+        }
+        if let Some(member) = &args.member {
+            // The workspace manifest is not at the root of the skeleton if `--workspace-root`
+            // points to one of its parent directories.
+            let workspace_manifest =
+                diff_paths(metadata.workspace_root.join("Cargo.toml"), &base_path)
+                    .unwrap_or_else(|| PathBuf::from("Cargo.toml"));
+            ignore_all_members_except(&mut manifests, &metadata, &workspace_manifest, member)?;
+            clear_dangling_default_run(&mut manifests);
+        }
+        if !args.excluded_bins.is_empty() {
+            exclude_bins(&mut manifests, &args.excluded_bins)?;
+        }
+        if args.minify {
+            minify::strip_non_essential_sections(&mut manifests);
         }
 
+        let mut extra_files = match &args.build_inputs {
+            Some(patterns) => read::build_inputs(&base_path, &manifests, patterns)?
+                .into_iter()
+                .map(|file| ExtraFile {
+                    contents: normalize_line_endings(file.contents),
+                    ..file
+                })
+                .collect(),
+            None => vec![],
+        };
+        let tool_config_files = match &args.tool_config_files {
+            Some(paths) => read::tool_config_files(&base_path, paths)?,
+            None => read::tool_config_files(&base_path, DEFAULT_TOOL_CONFIG_FILES)?,
+        };
+        extra_files.extend(tool_config_files.into_iter().map(|file| ExtraFile {
+            contents: normalize_line_endings(file.contents),
+            ..file
+        }));
+        extra_files.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+        extra_files.dedup_by(|a, b| a.relative_path == b.relative_path);
+
         let mut lock_file = read::lockfile(&base_path)?;
-        let rust_toolchain_file = read::rust_toolchain(&base_path)?;
+        let rust_toolchain_file = read::rust_toolchain(&base_path)?
+            .map(|(file_kind, contents)| (file_kind, normalize_line_endings(contents)));
 
         version_masking::mask_local_crate_versions(&mut manifests, &mut lock_file);
 
-        let lock_file = lock_file.map(|l| toml::to_string(&l)).transpose()?;
+        let lock_file = lock_file
+            .map(|l| toml::to_string(&l))
+            .transpose()?
+            .map(normalize_line_endings);
 
         let mut serialised_manifests = serialize_manifests(manifests)?;
+        if args.nested_projects {
+            for directory in read::nested_projects(&base_path, &serialised_manifests)? {
+                let nested_args = PrepareArgs {
+                    minify: args.minify,
+                    build_inputs: args.build_inputs.clone(),
+                    tool_config_files: Some(vec![]),
+                    ..Default::default()
+                };
+                let nested = Skeleton::derive_with_args(base_path.join(&directory), &nested_args)?;
+                nest_skeleton(
+                    nested,
+                    &directory,
+                    &mut serialised_manifests,
+                    &mut extra_files,
+                );
+            }
+            extra_files.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+        }
         // We don't want an ordering issue (e.g. related to how files are read from the filesystem)
         // to make our skeleton generation logic non-reproducible - therefore we sort!
         serialised_manifests.sort_by_key(|m| m.relative_path.clone());
@@ -73,6 +206,7 @@ impl Skeleton {
             config_file,
             lock_file,
             rust_toolchain_file,
+            extra_files,
         })
     }
 
@@ -87,6 +221,42 @@ impl Skeleton {
         base_path: &Path,
         no_std: bool,
     ) -> Result<(), anyhow::Error> {
+        self.build_minimum_project_with_args(
+            base_path,
+            &MinimumProjectArgs {
+                no_std,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Same as [`Skeleton::build_minimum_project`], with more control over the dummy entrypoints.
+    pub fn build_minimum_project_with_args(
+        &self,
+        base_path: &Path,
+        args: &MinimumProjectArgs,
+    ) -> Result<(), anyhow::Error> {
+        let no_std = args.no_std;
+        // Validate the recipe before writing anything, to avoid leaving a partially
+        // rehydrated project behind.
+        for manifest in &self.manifests {
+            for target in &manifest.targets {
+                let relative_path = manifest
+                    .relative_path
+                    .parent()
+                    .unwrap_or_else(|| Path::new(""))
+                    .join(&target.path);
+                if escapes_root(&relative_path) {
+                    anyhow::bail!(
+                        "The entrypoint of `{}` ({}) is outside of the project root: a dummy \
+                        can't be created for it.",
+                        target.name,
+                        relative_path.display()
+                    );
+                }
+            }
+        }
+
         // Save lockfile to disk, if available
         if let Some(lock_file) = &self.lock_file {
             let lock_file_path = base_path.join("Cargo.lock");
@@ -111,13 +281,18 @@ impl Skeleton {
             fs::write(config_file_path, config_file.as_str())?;
         }
 
+        for extra_file in &self.extra_files {
+            let path = base_path.join(&extra_file.relative_path);
+            if let Some(parent_dir) = path.parent() {
+                fs::create_dir_all(parent_dir)?;
+            }
+            fs::write(path, extra_file.contents.as_str())?;
+        }
+
+        // The `no_std` binaries and harnesses are followed by the panic handler, unless the
+        // project relies on the one provided by a dependency.
         const NO_STD_ENTRYPOINT: &str = "#![no_std]
 #![no_main]
-
-#[panic_handler]
-fn panic(_: &core::panic::PanicInfo) -> ! {
-    loop {}
-}
 ";
         const NO_STD_HARNESS_ENTRYPOINT: &str = r#"#![no_std]
 #![no_main]
@@ -128,22 +303,66 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
 pub extern "C" fn _init() {}
 
 fn test_runner(_: &[&dyn Fn()]) {}
+"#;
+        // Starting from edition 2024, `no_mangle` must be marked as `unsafe`.
+        const NO_STD_HARNESS_ENTRYPOINT_2024: &str = r#"#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(test_runner)]
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _init() {}
 
+fn test_runner(_: &[&dyn Fn()]) {}
+"#;
+        const PANIC_HANDLER: &str = "
 #[panic_handler]
 fn panic(_: &core::panic::PanicInfo) -> ! {
     loop {}
 }
-"#;
+";
+        let panic_handler = if args.no_panic_handler {
+            ""
+        } else {
+            PANIC_HANDLER
+        };
+        let no_std_entrypoint = format!("{}{}", NO_STD_ENTRYPOINT, panic_handler);
+        let no_std_harness_entrypoint = format!("{}{}", NO_STD_HARNESS_ENTRYPOINT, panic_handler);
+        let no_std_harness_entrypoint_2024 =
+            format!("{}{}", NO_STD_HARNESS_ENTRYPOINT_2024, panic_handler);
 
-        let get_test_like_entrypoint = |harness: bool| -> &str {
+        // The custom test harness relies on unstable features: on stable toolchains we fall back
+        // to a bare `#![no_std]` stub, which compiles as long as `std` is available for the target.
+        const NO_STD_STABLE_HARNESS_ENTRYPOINT: &str = "#![no_std]";
+        // Probing the toolchain is expensive, so we only do it if we have to.
+        let is_nightly_toolchain = std::cell::OnceCell::new();
+        let get_test_like_entrypoint = |no_std: bool, harness: bool, edition: u32| -> &str {
             match (no_std, harness) {
-                (true, true) => NO_STD_HARNESS_ENTRYPOINT,
-                (true, false) => NO_STD_ENTRYPOINT,
+                (true, true) => {
+                    if !*is_nightly_toolchain.get_or_init(|| self.is_nightly_toolchain(base_path)) {
+                        NO_STD_STABLE_HARNESS_ENTRYPOINT
+                    } else if edition >= 2024 {
+                        &no_std_harness_entrypoint_2024
+                    } else {
+                        &no_std_harness_entrypoint
+                    }
+                }
+                (true, false) => &no_std_entrypoint,
                 (false, true) => "",
                 (false, false) => "fn main() {}",
             }
         };
 
+        // The edition members inherit via `edition.workspace = true`.
+        let mut workspace_edition = None;
+        for manifest in &self.manifests {
+            if let Some(edition) =
+                declared_editions(&manifest.contents)?.remove("workspace.package")
+            {
+                workspace_edition = Some(edition);
+            }
+        }
+
         // Save all manifests to disks
         for manifest in &self.manifests {
             // Persist manifest
@@ -155,8 +374,21 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
                 base_path.to_path_buf()
             };
             fs::write(&manifest_path, &manifest.contents)?;
-            let parsed_manifest =
-                cargo_manifest::Manifest::from_slice(manifest.contents.as_bytes())?;
+            let (parsed_manifest, _) = parse_manifest(&manifest.contents)?;
+            let no_std = no_std_override(&parsed_manifest).unwrap_or(no_std);
+            let editions = declared_editions(&manifest.contents)?;
+            let edition = |table: String| -> u32 {
+                let edition = match editions.get(&table).or_else(|| editions.get("package")) {
+                    // The edition is inherited from the workspace.
+                    Some(edition) if edition.is_table() => workspace_edition.as_ref(),
+                    edition => edition,
+                };
+                // `cargo` falls back to edition 2015 if none is specified.
+                edition
+                    .and_then(|edition| edition.as_str())
+                    .and_then(|edition| edition.parse().ok())
+                    .unwrap_or(2015)
+            };
 
             let is_harness = |products: &Option<Vec<Product>>, name: &str| -> bool {
                 products
@@ -172,10 +404,13 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
             // Create dummy entrypoints for all targets
             for target in &manifest.targets {
                 let content = match target.kind {
+                    // A build script that emits nothing satisfies the `links` contract as well:
+                    // emitting `cargo:rustc-link-lib` would require the native library to be
+                    // available when cooking.
                     TargetKind::BuildScript => "fn main() {}",
                     TargetKind::Bin | TargetKind::Example => {
                         if no_std {
-                            NO_STD_ENTRYPOINT
+                            &no_std_entrypoint
                         } else {
                             "fn main() {}"
                         }
@@ -187,12 +422,16 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
                             ""
                         }
                     }
-                    TargetKind::Bench => {
-                        get_test_like_entrypoint(is_harness(&parsed_manifest.bench, &target.name))
-                    }
-                    TargetKind::Test => {
-                        get_test_like_entrypoint(is_harness(&parsed_manifest.test, &target.name))
-                    }
+                    TargetKind::Bench => get_test_like_entrypoint(
+                        no_std,
+                        is_harness(&parsed_manifest.bench, &target.name),
+                        edition(format!("bench.{}", target.name)),
+                    ),
+                    TargetKind::Test => get_test_like_entrypoint(
+                        no_std,
+                        is_harness(&parsed_manifest.test, &target.name),
+                        edition(format!("test.{}", target.name)),
+                    ),
                 };
                 let path = parent_directory.join(&target.path);
                 if let Some(dir) = path.parent() {
@@ -204,21 +443,545 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
         Ok(())
     }
 
-    /// Scan the target directory and remove all compilation artifacts for libraries and build
-    /// scripts from the current workspace.
-    /// Given the usage of dummy `lib.rs` and `build.rs` files, keeping them around leads to funny
-    /// compilation errors.
-    pub fn remove_compiled_dummies<P: AsRef<Path>>(
+    /// Override the `crate-type` of the library target of `package`.
+    ///
+    /// All the compilation artifacts of a library are cleaned up by
+    /// [`Skeleton::remove_compiled_dummies`], regardless of its crate type.
+    pub fn set_crate_type(
+        &mut self,
+        package: &str,
+        crate_types: &[String],
+    ) -> Result<(), anyhow::Error> {
+        let manifest = self
+            .manifests
+            .iter_mut()
+            .find(|manifest| package_name(manifest).as_deref() == Some(package))
+            .ok_or_else(|| {
+                anyhow::anyhow!("There is no package named `{}` in the recipe.", package)
+            })?;
+        let mut contents: toml::Value = toml::from_str(&manifest.contents)?;
+        let lib = contents
+            .get_mut("lib")
+            .and_then(|lib| lib.as_table_mut())
+            .ok_or_else(|| anyhow::anyhow!("`{}` does not have a library target.", package))?;
+        lib.insert(
+            "crate-type".to_string(),
+            toml::Value::Array(
+                crate_types
+                    .iter()
+                    .map(|crate_type| toml::Value::String(crate_type.to_owned()))
+                    .collect(),
+            ),
+        );
+        manifest.contents = toml::to_string(&contents)?;
+        Ok(())
+    }
+
+    /// The manifest of the package named `package_name`, if it's part of the skeleton.
+    pub fn manifest_for(&self, package_name: &str) -> Option<&Manifest> {
+        self.manifests
+            .iter()
+            .find(|manifest| self::package_name(manifest).as_deref() == Some(package_name))
+    }
+
+    /// How many packages `cargo` builds when none is selected explicitly: the `default-members`
+    /// of the workspace if they are set, otherwise the root package or, for a virtual manifest,
+    /// all members.
+    pub fn default_member_count(&self) -> Result<usize, anyhow::Error> {
+        let root = match self.workspace_manifest() {
+            Some(root) => root,
+            None => return Ok(0),
+        };
+        let contents: toml::Value = toml::from_str(&root.contents)?;
+        let default_members = contents
+            .get("workspace")
+            .and_then(|workspace| workspace.get("default-members"))
+            .and_then(|default_members| default_members.as_array());
+        Ok(match default_members {
+            Some(default_members) => default_members.len(),
+            None if contents.get("package").is_some() => 1,
+            None => self
+                .manifests
+                .iter()
+                .filter(|manifest| package_name(manifest).is_some())
+                .count(),
+        })
+    }
+
+    /// The directories that replace a source in the `cargo` configuration of the recipe (e.g.
+    /// `cargo vendor`'s `directory = "vendor"`), resolved against `base_path` like `cargo` does.
+    /// They are not part of the recipe: they must be copied in before cooking.
+    pub fn replacement_source_directories(
         &self,
-        base_path: P,
+        base_path: &Path,
+    ) -> Result<Vec<PathBuf>, anyhow::Error> {
+        let config: toml::Value = match &self.config_file {
+            Some(config_file) => toml::from_str(config_file)?,
+            None => return Ok(vec![]),
+        };
+        let sources = match config.get("source").and_then(|sources| sources.as_table()) {
+            Some(sources) => sources,
+            None => return Ok(vec![]),
+        };
+        let mut directories: Vec<PathBuf> = sources
+            .values()
+            .filter_map(|source| source.get("replace-with"))
+            .filter_map(|replacement| replacement.as_str())
+            .filter_map(|replacement| sources.get(replacement))
+            .filter_map(|replacement| replacement.get("directory"))
+            .filter_map(|directory| directory.as_str())
+            .map(|directory| base_path.join(directory))
+            .collect();
+        directories.sort();
+        directories.dedup();
+        Ok(directories)
+    }
+
+    /// The manifest at the root of the workspace.
+    ///
+    /// It's not at the root of the skeleton if the skeleton was rooted in one of its parent
+    /// directories (see [`PrepareArgs::workspace_root`]): we then look for the outermost manifest
+    /// with a `[workspace]` section.
+    fn workspace_manifest(&self) -> Option<&Manifest> {
+        if let Some(root) = self
+            .manifests
+            .iter()
+            .find(|manifest| manifest.relative_path == Path::new("Cargo.toml"))
+        {
+            return Some(root);
+        }
+        self.manifests
+            .iter()
+            .filter(|manifest| {
+                toml::from_str::<toml::Value>(&manifest.contents)
+                    .is_ok_and(|contents| contents.get("workspace").is_some())
+            })
+            .min_by_key(|manifest| manifest.relative_path.components().count())
+    }
+
+    /// Whether any manifest in the skeleton defines a package, i.e. if there is anything for
+    /// `cargo` to build. A brand-new workspace might have no members yet.
+    pub fn has_packages(&self) -> bool {
+        self.manifests
+            .iter()
+            .any(|manifest| package_name(manifest).is_some())
+    }
+
+    /// Add `features` to the `default` feature of `package`, to get the dependencies they
+    /// activate compiled and cached even if `cargo` can't be asked to enable them via `--features`.
+    ///
+    /// It's applied by `cook`, on top of the recipe: it has no influence on version masking,
+    /// which happens during `prepare`, and the recipe (hence its Docker layer) is unchanged.
+    pub fn enable_features(
+        &mut self,
+        package: &str,
+        features: &[String],
+    ) -> Result<(), anyhow::Error> {
+        let declared_features = self.declared_features()?;
+        let package_features = declared_features.get(package).ok_or_else(|| {
+            anyhow::anyhow!("There is no package named `{}` in the recipe.", package)
+        })?;
+        if let Some(feature) = features
+            .iter()
+            .find(|feature| !package_features.contains(*feature))
+        {
+            anyhow::bail!(
+                "`{}` does not declare a feature named `{}`.",
+                package,
+                feature
+            );
+        }
+        let manifest = self
+            .manifests
+            .iter_mut()
+            .find(|manifest| package_name(manifest).as_deref() == Some(package))
+            .expect("The package was found among the declared features");
+        let mut contents: toml::Value = toml::from_str(&manifest.contents)?;
+        let default_features = contents
+            .as_table_mut()
+            .and_then(|contents| {
+                contents
+                    .entry("features")
+                    .or_insert_with(|| toml::Value::Table(Default::default()))
+                    .as_table_mut()
+            })
+            .and_then(|table| {
+                table
+                    .entry("default")
+                    .or_insert_with(|| toml::Value::Array(vec![]))
+                    .as_array_mut()
+            })
+            .ok_or_else(|| {
+                anyhow::anyhow!("The `[features]` table of `{}` is invalid.", package)
+            })?;
+        for feature in features {
+            let feature = toml::Value::String(feature.to_owned());
+            if !default_features.contains(&feature) {
+                default_features.push(feature);
+            }
+        }
+        manifest.contents = toml::to_string(&contents)?;
+        Ok(())
+    }
+
+    /// The features that can be activated for each package in the recipe, keyed by package name:
+    /// the ones declared in `[features]` and the implicit ones of optional dependencies.
+    pub fn declared_features(&self) -> Result<BTreeMap<String, BTreeSet<String>>, anyhow::Error> {
+        let mut declared_features = BTreeMap::new();
+        for manifest in &self.manifests {
+            let contents: toml::Value = toml::from_str(&manifest.contents)?;
+            let name = match contents
+                .get("package")
+                .and_then(|package| package.get("name"))
+                .and_then(|name| name.as_str())
+            {
+                Some(name) => name.to_owned(),
+                None => continue,
+            };
+            let mut features: BTreeSet<String> = contents
+                .get("features")
+                .and_then(|features| features.as_table())
+                .map(|features| features.keys().cloned().collect())
+                .unwrap_or_default();
+            let targets = contents
+                .get("target")
+                .and_then(|targets| targets.as_table())
+                .into_iter()
+                .flat_map(|targets| targets.values());
+            for table in std::iter::once(&contents).chain(targets) {
+                for section in ["dependencies", "build-dependencies"] {
+                    let dependencies = table
+                        .get(section)
+                        .and_then(|dependencies| dependencies.as_table())
+                        .into_iter()
+                        .flatten();
+                    for (dependency_name, dependency) in dependencies {
+                        if dependency.get("optional").and_then(|o| o.as_bool()) == Some(true) {
+                            features.insert(dependency_name.to_owned());
+                        }
+                    }
+                }
+            }
+            declared_features.insert(name, features);
+        }
+        Ok(declared_features)
+    }
+
+    /// Add `features` to the `default` feature of all the packages declaring them, see
+    /// [`Skeleton::enable_features`]. Packages lacking some of the features only get the ones
+    /// they declare, but we fail if a feature is not declared by any package.
+    pub fn enable_workspace_features(&mut self, features: &[String]) -> Result<(), anyhow::Error> {
+        self.check_features(features)?;
+        for (package, package_features) in self.declared_features()? {
+            let features: Vec<String> = features
+                .iter()
+                .filter(|feature| package_features.contains(*feature))
+                .cloned()
+                .collect();
+            if !features.is_empty() {
+                self.enable_features(&package, &features)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fail if any of the `features` requested to `cook` is not declared by the packages in the
+    /// recipe, instead of letting `cargo` fail after the skeleton has been rehydrated.
+    ///
+    /// Features of dependencies (`dependency/feature`) are not checked.
+    pub fn check_features<'a, I>(&self, features: I) -> Result<(), anyhow::Error>
+    where
+        I: IntoIterator<Item = &'a String>,
+    {
+        let declared_features = self.declared_features()?;
+        for feature in features {
+            if feature == "default" {
+                continue;
+            }
+            match feature.split_once('/') {
+                Some((package, package_feature)) => {
+                    if let Some(package_features) = declared_features.get(package) {
+                        if package_feature != "default"
+                            && !package_features.contains(package_feature)
+                        {
+                            anyhow::bail!(
+                                "`{}` does not declare a feature named `{}`.",
+                                package,
+                                package_feature
+                            );
+                        }
+                    }
+                }
+                None => {
+                    if !declared_features
+                        .values()
+                        .any(|package_features| package_features.contains(feature))
+                    {
+                        anyhow::bail!(
+                            "There is no feature named `{}` in the recipe. Did you misspell it?",
+                            feature
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The external dependencies that the packages in the recipe declare under `[dependencies]`,
+    /// as `<name>@<version>` package specs resolved against the lock file.
+    ///
+    /// Optional, platform-specific, build and dev dependencies are left out: `cargo build
+    /// --package` only accepts packages that are unconditionally part of the build.
+    pub fn external_dependencies(&self) -> Result<Vec<String>, anyhow::Error> {
+        let lock_file = match &self.lock_file {
+            Some(lock_file) => lock_file,
+            None => anyhow::bail!(
+                "The recipe has no lockfile: the dependencies of the workspace can't be resolved."
+            ),
+        };
+        let lock_file: toml::Value =
+            toml::from_str(lock_file).context("Failed to parse Cargo.lock")?;
+        let packages = lock_file
+            .get("package")
+            .and_then(|packages| packages.as_array())
+            .map(|packages| packages.as_slice())
+            .unwrap_or_default();
+        let field = |package: &toml::Value, key: &str| {
+            package
+                .get(key)
+                .and_then(|value| value.as_str())
+                .map(|value| value.to_owned())
+        };
+
+        let mut contents = vec![];
+        for manifest in &self.manifests {
+            contents.push(toml::from_str::<toml::Value>(&manifest.contents)?);
+        }
+        // Dependencies inherited from the workspace might have been renamed there.
+        let mut workspace_renames = BTreeMap::new();
+        for contents in &contents {
+            let dependencies = contents
+                .get("workspace")
+                .and_then(|workspace| workspace.get("dependencies"))
+                .and_then(|dependencies| dependencies.as_table())
+                .into_iter()
+                .flatten();
+            for (dependency_name, dependency) in dependencies {
+                if let Some(package) = field(dependency, "package") {
+                    workspace_renames.insert(dependency_name.to_owned(), package);
+                }
+            }
+        }
+
+        let mut specs = BTreeSet::new();
+        for contents in &contents {
+            let name = match contents
+                .get("package")
+                .and_then(|package| package.get("name"))
+                .and_then(|name| name.as_str())
+            {
+                Some(name) => name,
+                None => continue,
+            };
+            let dependencies = contents
+                .get("dependencies")
+                .and_then(|dependencies| dependencies.as_table())
+                .into_iter()
+                .flatten();
+            let mut declared = BTreeSet::new();
+            for (dependency_name, dependency) in dependencies {
+                if dependency.get("optional").and_then(|o| o.as_bool()) == Some(true) {
+                    continue;
+                }
+                let is_inherited =
+                    dependency.get("workspace").and_then(|w| w.as_bool()) == Some(true);
+                let package = match field(dependency, "package") {
+                    Some(package) => package,
+                    None if is_inherited => workspace_renames
+                        .get(dependency_name)
+                        .cloned()
+                        .unwrap_or_else(|| dependency_name.to_owned()),
+                    None => dependency_name.to_owned(),
+                };
+                declared.insert(package);
+            }
+            // Local packages have no `source` in the lock file.
+            let locked_dependencies = packages
+                .iter()
+                .filter(|package| {
+                    field(package, "name").as_deref() == Some(name)
+                        && package.get("source").is_none()
+                })
+                .filter_map(|package| package.get("dependencies"))
+                .filter_map(|dependencies| dependencies.as_array())
+                .flatten()
+                .filter_map(|dependency| dependency.as_str());
+            for dependency in locked_dependencies {
+                // Either `<name>`, `<name> <version>` or `<name> <version> (<source>)`.
+                let mut parts = dependency.split(' ');
+                let dependency_name = parts.next().unwrap_or_default();
+                let version = parts.next();
+                if !declared.contains(dependency_name) {
+                    continue;
+                }
+                let locked = packages.iter().find(|package| {
+                    field(package, "name").as_deref() == Some(dependency_name)
+                        && version.is_none_or(|version| {
+                            field(package, "version").as_deref() == Some(version)
+                        })
+                });
+                if let Some(locked) = locked.filter(|locked| locked.get("source").is_some()) {
+                    if let Some(version) = field(locked, "version") {
+                        specs.insert(format!("{}@{}", dependency_name, version));
+                    }
+                }
+            }
+        }
+        Ok(specs.into_iter().collect())
+    }
+
+    /// Only keep the tests, benches and examples that are going to be compiled by `cargo` given
+    /// the target selection flags in `target_args`.
+    /// `cargo` does not complain about missing entrypoints for targets it does not build, hence
+    /// there is no need to create dummy files for them.
+    /// The exception are targets declared in the manifest without a `path` (e.g. a `[[bench]]`
+    /// with `harness = false`): `cargo` refuses to load the manifest if it can't infer their
+    /// entrypoint.
+    pub fn retain_targets(&mut self, target_args: &TargetArgs) {
+        for manifest in &mut self.manifests {
+            let contents: Option<toml::Value> = toml::from_str(&manifest.contents).ok();
+            let is_declared_without_path = |table: &str, name: &str| -> bool {
+                contents
+                    .as_ref()
+                    .and_then(|contents| contents.get(table)?.as_array())
+                    .is_some_and(|targets| {
+                        targets.iter().any(|target| {
+                            target.get("name").and_then(|n| n.as_str()) == Some(name)
+                                && target.get("path").is_none()
+                        })
+                    })
+            };
+            manifest.targets.retain(|target| {
+                target_args.all_targets
+                    || match target.kind {
+                        TargetKind::Test => {
+                            target_args.tests || is_declared_without_path("test", &target.name)
+                        }
+                        TargetKind::Bench => {
+                            target_args.benches || is_declared_without_path("bench", &target.name)
+                        }
+                        TargetKind::Example => {
+                            target_args.examples
+                                || target_args
+                                    .example
+                                    .as_ref()
+                                    .is_some_and(|examples| examples.contains(&target.name))
+                                || is_declared_without_path("example", &target.name)
+                        }
+                        TargetKind::Lib { .. } | TargetKind::Bin | TargetKind::BuildScript => true,
+                    }
+            });
+        }
+    }
+
+    /// List all the files that [`Skeleton::build_minimum_project`] is going to create (or
+    /// overwrite!) when invoked with the same `base_path`.
+    ///
+    /// The returned paths are sorted and deduplicated.
+    pub fn planned_files(&self, base_path: &Path) -> Vec<PathBuf> {
+        let mut files = vec![];
+        if self.lock_file.is_some() {
+            files.push(base_path.join("Cargo.lock"));
+        }
+        if let Some((file_kind, _)) = &self.rust_toolchain_file {
+            let file_name = match file_kind {
+                RustToolchainFile::Bare => "rust-toolchain",
+                RustToolchainFile::Toml => "rust-toolchain.toml",
+            };
+            files.push(base_path.join(file_name));
+        }
+        if self.config_file.is_some() {
+            files.push(base_path.join(".cargo").join("config.toml"));
+        }
+        for extra_file in &self.extra_files {
+            files.push(base_path.join(&extra_file.relative_path));
+        }
+        for manifest in &self.manifests {
+            let manifest_path = base_path.join(&manifest.relative_path);
+            let parent_directory = manifest_path.parent().unwrap_or(base_path).to_path_buf();
+            for target in &manifest.targets {
+                files.push(parent_directory.join(&target.path));
+            }
+            files.push(manifest_path);
+        }
+        files.sort();
+        files.dedup();
+        files
+    }
+
+    /// The directory `cargo` is going to store build artifacts in, when invoked from `base_path`.
+    ///
+    /// An explicit `target_dir` (i.e. `--target-dir` or `CARGO_TARGET_DIR`) takes precedence
+    /// over `CARGO_BUILD_TARGET_DIR`, then over `build.target-dir` in the configuration file,
+    /// which takes precedence over the default, `target`.
+    ///
+    /// Just like `cargo`, we resolve a relative explicit `target_dir` with respect to the current
+    /// directory, regardless of `base_path` and `--manifest-path`.
+    pub(crate) fn resolve_target_dir(
+        &self,
+        base_path: &Path,
+        target_dir: Option<PathBuf>,
+    ) -> PathBuf {
+        if let Some(target_dir) = target_dir {
+            return match std::env::current_dir() {
+                Ok(current_directory) => current_directory.join(target_dir),
+                Err(_) => target_dir,
+            };
+        }
+        self.configured_target_dir(base_path)
+            .unwrap_or_else(|| base_path.join("target"))
+    }
+
+    /// The `build.target-dir` set via `CARGO_BUILD_TARGET_DIR` or, failing that, in the `cargo`
+    /// configuration file of the recipe, if any.
+    pub(crate) fn configured_target_dir(&self, base_path: &Path) -> Option<PathBuf> {
+        if let Some(target_dir) = std::env::var_os("CARGO_BUILD_TARGET_DIR") {
+            if !target_dir.is_empty() {
+                // Like an explicit `target_dir`, it's resolved with respect to the current
+                // directory.
+                let target_dir = PathBuf::from(target_dir);
+                return Some(match std::env::current_dir() {
+                    Ok(current_directory) => current_directory.join(target_dir),
+                    Err(_) => target_dir,
+                });
+            }
+        }
+        let target_dir = self
+            .config_file
+            .as_ref()
+            .and_then(|config| toml::from_str::<toml::Value>(config).ok())
+            .and_then(|config| {
+                config
+                    .get("build")?
+                    .get("target-dir")?
+                    .as_str()
+                    .map(PathBuf::from)
+            })?;
+        // Relative paths are resolved with respect to the parent of the `.cargo` directory.
+        Some(base_path.join(target_dir))
+    }
+
+    /// The directories, one per target triple, storing the artifacts compiled with `profile`.
+    /// Only the ones that exist are returned.
+    fn profile_directories(
+        &self,
+        base_path: &Path,
         profile: OptimisationProfile,
         target: Option<Vec<String>>,
         target_dir: Option<PathBuf>,
-    ) -> Result<(), anyhow::Error> {
-        let target_dir = match target_dir {
-            None => base_path.as_ref().join("target"),
-            Some(target_dir) => target_dir,
-        };
+    ) -> Vec<PathBuf> {
+        let target_dir = self.resolve_target_dir(base_path, target_dir);
 
         let profile = match profile {
             OptimisationProfile::Release => "release".to_string(),
@@ -226,24 +989,124 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
             OptimisationProfile::Other(custom_profile) => custom_profile,
         };
 
-        let target_directories: Vec<PathBuf> = target
+        // When cross-compiling, host artifacts (e.g. build scripts) are still stored in the
+        // top-level profile directory, next to the target-specific ones.
+        target
             .map_or(vec![target_dir.clone()], |targets| {
-                targets
-                    .iter()
-                    .map(|target| target_dir.join(target_str(target)))
+                std::iter::once(target_dir.clone())
+                    .chain(
+                        targets
+                            .iter()
+                            .map(|target| target_dir.join(target_str(target))),
+                    )
                     .collect()
             })
             .iter()
             .map(|path| path.join(&profile))
-            .collect();
+            .filter(|path| path.exists())
+            .collect()
+    }
+
+    /// Look for compiled dummy libraries that are still around after
+    /// [`Skeleton::remove_compiled_dummies`], using broader patterns than the cleanup (e.g.
+    /// artifacts without the `lib` prefix, as they are named on Windows).
+    /// Dep-info (`.d`) files are ignored: they don't affect the following builds.
+    pub fn find_compiled_dummies<P: AsRef<Path>>(
+        &self,
+        base_path: P,
+        profile: OptimisationProfile,
+        target: Option<Vec<String>>,
+        target_dir: Option<PathBuf>,
+    ) -> Result<Vec<PathBuf>, anyhow::Error> {
+        let target_directories =
+            self.profile_directories(base_path.as_ref(), profile, target, target_dir);
+        let mut leftovers = vec![];
+        for manifest in &self.manifests {
+            let (parsed_manifest, _) = parse_manifest(&manifest.contents)?;
+            if let (Some(package), Some(lib)) = (&parsed_manifest.package, &parsed_manifest.lib) {
+                let library_name = lib.name.as_ref().unwrap_or(&package.name).replace('-', "_");
+                for target_directory in &target_directories {
+                    // Only library artifacts: binaries sharing the library name (e.g. `foo.exe`,
+                    // `foo.pdb`) are real artifacts, not dummies.
+                    let mut patterns = vec![];
+                    for extension in LIBRARY_EXTENSIONS {
+                        for prefix in ["lib", ""] {
+                            patterns.push(format!("/**/{}{}.{}", prefix, library_name, extension));
+                            patterns
+                                .push(format!("/**/{}{}-*.{}", prefix, library_name, extension));
+                        }
+                    }
+                    patterns.push("!/.fingerprint".to_string());
+                    patterns.push("!/incremental".to_string());
+                    let walker =
+                        GlobWalkerBuilder::from_patterns(target_directory, &patterns).build()?;
+                    for file in walker {
+                        let file = file?;
+                        if file.file_type().is_file() {
+                            leftovers.push(file.path().to_path_buf());
+                        }
+                    }
+                }
+            }
+        }
+        leftovers.sort();
+        Ok(leftovers)
+    }
+
+    /// Determine if dependencies are going to be cooked using a nightly toolchain.
+    ///
+    /// The channel pinned in the rust toolchain file takes precedence, if there is one.
+    /// Otherwise we ask `rustc` directly.
+    fn is_nightly_toolchain(&self, base_path: &Path) -> bool {
+        let is_nightly_channel = |channel: &str| channel.trim().starts_with("nightly");
+        let pinned_channel = match &self.rust_toolchain_file {
+            Some((RustToolchainFile::Bare, content)) => Some(is_nightly_channel(content)),
+            Some((RustToolchainFile::Toml, content)) => toml::from_str::<toml::Value>(content)
+                .ok()
+                .and_then(|toolchain| {
+                    toolchain
+                        .get("toolchain")?
+                        .get("channel")?
+                        .as_str()
+                        .map(is_nightly_channel)
+                }),
+            None => None,
+        };
+        pinned_channel.unwrap_or_else(|| {
+            let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+            std::process::Command::new(rustc)
+                .arg("--version")
+                .current_dir(base_path)
+                .output()
+                .map(|output| {
+                    let version = String::from_utf8_lossy(&output.stdout);
+                    version.contains("-nightly") || version.contains("-dev")
+                })
+                .unwrap_or(false)
+        })
+    }
+
+    /// Scan the target directory and remove all compilation artifacts for libraries and build
+    /// scripts from the current workspace.
+    /// Given the usage of dummy `lib.rs` and `build.rs` files, keeping them around leads to funny
+    /// compilation errors.
+    pub fn remove_compiled_dummies<P: AsRef<Path>>(
+        &self,
+        base_path: P,
+        profile: OptimisationProfile,
+        target: Option<Vec<String>>,
+        target_dir: Option<PathBuf>,
+    ) -> Result<(), anyhow::Error> {
+        let target_directories =
+            self.profile_directories(base_path.as_ref(), profile, target, target_dir);
 
         for manifest in &self.manifests {
-            let parsed_manifest =
-                cargo_manifest::Manifest::from_slice(manifest.contents.as_bytes())?;
+            let (parsed_manifest, _) = parse_manifest(&manifest.contents)?;
             if let Some(package) = parsed_manifest.package.as_ref() {
                 for target_directory in &target_directories {
-                    // Remove dummy libraries.
-                    for lib in &parsed_manifest.lib {
+                    // Remove dummy libraries, including the `.rmeta` files emitted by `cargo check`.
+                    // `cdylib`s compiled to WebAssembly are not prefixed with `lib`.
+                    if let Some(lib) = &parsed_manifest.lib {
                         let library_name =
                             lib.name.as_ref().unwrap_or(&package.name).replace('-', "_");
                         let walker = GlobWalkerBuilder::from_patterns(
@@ -251,6 +1114,8 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
                             &[
                                 format!("/**/lib{}.*", library_name),
                                 format!("/**/lib{}-*", library_name),
+                                format!("/**/{}.wasm", library_name),
+                                format!("/**/{}-*.wasm", library_name),
                             ],
                         )
                         .build()?;
@@ -276,6 +1141,28 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
                             fs::remove_file(file.path())?;
                         }
                     }
+
+                    // Remove dummy examples.
+                    for target in &manifest.targets {
+                        if target.kind != TargetKind::Example {
+                            continue;
+                        }
+                        let walker = GlobWalkerBuilder::from_patterns(
+                            target_directory,
+                            &[
+                                format!("/examples/{}", target.name),
+                                format!("/examples/{}.*", target.name),
+                                format!("/examples/{}-*", target.name.replace('-', "_")),
+                            ],
+                        )
+                        .build()?;
+                        for file in walker {
+                            let file = file?;
+                            if file.file_type().is_file() {
+                                fs::remove_file(file.path())?;
+                            }
+                        }
+                    }
                 }
             }
         }
@@ -284,6 +1171,142 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
     }
 }
 
+/// Whether `relative_path` points outside of the directory it is relative to, e.g.
+/// `../shared/tool.rs`. The check is purely lexical: symlinks are not resolved.
+fn escapes_root(relative_path: &Path) -> bool {
+    let mut depth = 0usize;
+    for component in relative_path.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir => match depth.checked_sub(1) {
+                Some(parent_depth) => depth = parent_depth,
+                None => return true,
+            },
+            Component::RootDir | Component::Prefix(_) => return true,
+        }
+    }
+    false
+}
+
+/// The name of the package defined by `manifest`, if any.
+fn package_name(manifest: &Manifest) -> Option<String> {
+    let contents: toml::Value = toml::from_str(&manifest.contents).ok()?;
+    contents
+        .get("package")?
+        .get("name")?
+        .as_str()
+        .map(|name| name.to_owned())
+}
+
+/// The editions that `cargo_manifest` knows how to parse.
+const SUPPORTED_EDITIONS: &[&str] = &["2015", "2018", "2021"];
+
+/// An `edition` that `cargo_manifest` can't parse (e.g. `2024`), taken out of the table
+/// identified by the first element (e.g. `package` or `bin.server`).
+pub(in crate::skeleton) type UnsupportedEdition = (String, toml::Value);
+
+/// Parse `contents` with `cargo_manifest`, leaving out the editions it doesn't know about yet:
+/// they are returned, to be put back with [`restore_editions`] once the manifest is serialised.
+pub(in crate::skeleton) fn parse_manifest(
+    contents: &str,
+) -> Result<(cargo_manifest::Manifest, Vec<UnsupportedEdition>), anyhow::Error> {
+    let mut contents: toml::Value = toml::from_str(contents)?;
+    let mut editions = vec![];
+    for_each_edition_table(&mut contents, |id, table| {
+        let is_unsupported = table
+            .get("edition")
+            .and_then(|edition| edition.as_str())
+            .is_some_and(|edition| !SUPPORTED_EDITIONS.contains(&edition));
+        if is_unsupported {
+            editions.extend(table.remove("edition").map(|edition| (id, edition)));
+        }
+    });
+    Ok((contents.try_into()?, editions))
+}
+
+/// The `edition` keys of a manifest, keyed by the identifier of their table (e.g. `package` or
+/// `test.integration`). Editions inherited from the workspace are tables (`{ workspace = true }`).
+fn declared_editions(contents: &str) -> Result<HashMap<String, toml::Value>, anyhow::Error> {
+    let mut contents: toml::Value = toml::from_str(contents)?;
+    let mut editions = HashMap::new();
+    for_each_edition_table(&mut contents, |id, table| {
+        if let Some(edition) = table.get("edition") {
+            editions.insert(id, edition.clone());
+        }
+    });
+    Ok(editions)
+}
+
+/// Put back the editions taken out by [`parse_manifest`].
+pub(in crate::skeleton) fn restore_editions(
+    contents: &mut toml::Value,
+    editions: &[UnsupportedEdition],
+) {
+    for_each_edition_table(contents, |id, table| {
+        if let Some((_, edition)) = editions.iter().find(|(edition_id, _)| edition_id == &id) {
+            table.insert("edition".to_owned(), edition.clone());
+        }
+    });
+}
+
+/// Call `f` on all the tables of a manifest that can set an `edition`, together with an
+/// identifier that is stable across serialisation (targets are identified by name).
+fn for_each_edition_table(contents: &mut toml::Value, mut f: impl FnMut(String, &mut toml::Table)) {
+    let contents = match contents.as_table_mut() {
+        Some(contents) => contents,
+        None => return,
+    };
+    for (key, value) in contents.iter_mut() {
+        match key.as_str() {
+            "package" | "lib" => {
+                if let Some(table) = value.as_table_mut() {
+                    f(key.to_owned(), table);
+                }
+            }
+            "workspace" => {
+                if let Some(table) = value.get_mut("package").and_then(|p| p.as_table_mut()) {
+                    f("workspace.package".to_owned(), table);
+                }
+            }
+            "bin" | "example" | "test" | "bench" => {
+                let tables = value
+                    .as_array_mut()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|target| target.as_table_mut());
+                for table in tables {
+                    let name = table
+                        .get("name")
+                        .and_then(|name| name.as_str())
+                        .unwrap_or_default()
+                        .to_owned();
+                    f(format!("{}.{}", key, name), table);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Crates can opt in (or out) of `no_std` dummy entrypoints, regardless of the `--no-std` flag
+/// passed to `cook`, using their manifest metadata:
+///
+/// ```toml
+/// [package.metadata.cargo-chef]
+/// no-std = true
+/// ```
+fn no_std_override(manifest: &cargo_manifest::Manifest) -> Option<bool> {
+    manifest
+        .package
+        .as_ref()?
+        .metadata
+        .as_ref()?
+        .get("cargo-chef")?
+        .get("no-std")?
+        .as_bool()
+}
+
 /// If a custom target spec file is used,
 /// (Part of the unstable cargo feature 'build-std'; c.f. https://doc.rust-lang.org/rustc/targets/custom.html )
 /// the `--target` flag refers to a `.json` file in the current directory.
@@ -294,9 +1317,10 @@ fn target_str(target: &str) -> &str {
 
 fn serialize_manifests(manifests: Vec<ParsedManifest>) -> Result<Vec<Manifest>, anyhow::Error> {
     let mut serialised_manifests = vec![];
-    for manifest in manifests {
+    for mut manifest in manifests {
+        sort_features(&mut manifest.contents);
         // The serialised contents might be different from the original manifest!
-        let contents = toml::to_string(&manifest.contents)?;
+        let contents = normalize_line_endings(toml::to_string(&manifest.contents)?);
         serialised_manifests.push(Manifest {
             relative_path: manifest.relative_path,
             contents,
@@ -306,15 +1330,160 @@ fn serialize_manifests(manifests: Vec<ParsedManifest>) -> Result<Vec<Manifest>,
     Ok(serialised_manifests)
 }
 
-fn extract_cargo_metadata(path: &Path) -> Result<cargo_metadata::Metadata, anyhow::Error> {
+/// The order of the features in `[features]`, as well as the order of the entries enabled by
+/// each feature, has no effect on what gets built: we sort them to keep the recipe stable.
+fn sort_features(contents: &mut toml::Value) {
+    if let Some(features) = contents
+        .get_mut("features")
+        .and_then(|features| features.as_table_mut())
+    {
+        let mut sorted: Vec<(String, toml::Value)> = std::mem::take(features).into_iter().collect();
+        sorted.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (name, mut enabled) in sorted {
+            if let Some(enabled) = enabled.as_array_mut() {
+                enabled.sort_by(|a, b| a.as_str().cmp(&b.as_str()));
+            }
+            features.insert(name, enabled);
+        }
+    }
+}
+
+/// Recipes must be byte-for-byte identical regardless of the platform they were generated on,
+/// therefore we convert Windows-style line endings (CRLF) to LF in all captured files.
+fn normalize_line_endings(contents: String) -> String {
+    if contents.contains('\r') {
+        contents.replace("\r\n", "\n")
+    } else {
+        contents
+    }
+}
+
+/// Arguments that `cargo-chef` always passes to `cargo metadata`, hence they can't be
+/// specified via `--metadata-arg`.
+const RESERVED_METADATA_ARGS: &[&str] = &["--no-deps", "--format-version"];
+
+fn extract_cargo_metadata(
+    path: &Path,
+    extra_args: &[String],
+) -> Result<cargo_metadata::Metadata, anyhow::Error> {
+    if path.to_str().is_none() {
+        anyhow::bail!(
+            "`cargo` does not support paths that are not valid UTF-8, but the project is in {:?}.",
+            path
+        );
+    }
+    for arg in extra_args {
+        let flag = arg.split('=').next().unwrap_or(arg);
+        if RESERVED_METADATA_ARGS.contains(&flag) {
+            anyhow::bail!(
+                "`{}` is always passed to `cargo metadata` by `cargo-chef`, it can't be \
+                specified as an extra argument.",
+                flag
+            );
+        }
+    }
     let mut cmd = cargo_metadata::MetadataCommand::new();
     cmd.current_dir(path);
     cmd.no_deps();
+    cmd.other_options(extra_args.to_vec());
 
     cmd.exec().context("Cannot extract Cargo metadata")
 }
 
-/// If the top-level `Cargo.toml` has a `members` field, replace it with
+/// Merge the skeleton of an independent project, located in `directory`, into the manifests
+/// and extra files of the enclosing project.
+/// Its lock file is restored as an extra file, while its `.cargo/config.toml` and toolchain
+/// file are ignored: `cook` only picks up the ones at the root of the enclosing project.
+fn nest_skeleton(
+    nested: Skeleton,
+    directory: &Path,
+    manifests: &mut Vec<Manifest>,
+    extra_files: &mut Vec<ExtraFile>,
+) {
+    manifests.extend(nested.manifests.into_iter().map(|manifest| Manifest {
+        relative_path: directory.join(manifest.relative_path),
+        ..manifest
+    }));
+    extra_files.extend(nested.extra_files.into_iter().map(|file| ExtraFile {
+        relative_path: directory.join(file.relative_path),
+        ..file
+    }));
+    if let Some(lock_file) = nested.lock_file {
+        extra_files.push(ExtraFile {
+            relative_path: directory.join("Cargo.lock"),
+            contents: lock_file,
+        });
+    }
+}
+
+/// Drop the `bins` from the manifests and from the targets of their packages.
+/// `default-run` is removed as well if it points to one of them.
+fn exclude_bins(manifests: &mut [ParsedManifest], bins: &[String]) -> Result<(), anyhow::Error> {
+    for bin in bins {
+        let mut is_found = false;
+        for manifest in manifests.iter_mut() {
+            let targets_before = manifest.targets.len();
+            manifest
+                .targets
+                .retain(|target| !(target.kind == TargetKind::Bin && &target.name == bin));
+            if manifest.targets.len() == targets_before {
+                continue;
+            }
+            is_found = true;
+            if let Some(products) = manifest
+                .contents
+                .get_mut("bin")
+                .and_then(|products| products.as_array_mut())
+            {
+                products.retain(|product| {
+                    product.get("name").and_then(|name| name.as_str()) != Some(bin.as_str())
+                });
+            }
+            if let Some(package) = manifest
+                .contents
+                .get_mut("package")
+                .and_then(|package| package.as_table_mut())
+            {
+                if package.get("default-run").and_then(|d| d.as_str()) == Some(bin.as_str()) {
+                    package.remove("default-run");
+                }
+            }
+        }
+        if !is_found {
+            anyhow::bail!("There is no binary named `{}` in the workspace.", bin);
+        }
+    }
+    Ok(())
+}
+
+/// Remove `default-run` from the manifests that don't have a binary with that name.
+///
+/// `cook` never runs binaries, hence `default-run` has no effect on the build of the dependencies,
+/// but `cargo` refuses to load a manifest whose `default-run` points to a missing binary.
+fn clear_dangling_default_run(manifests: &mut [ParsedManifest]) {
+    for manifest in manifests {
+        let targets = &manifest.targets;
+        if let Some(package) = manifest
+            .contents
+            .get_mut("package")
+            .and_then(|package| package.as_table_mut())
+        {
+            let is_dangling = package
+                .get("default-run")
+                .and_then(|d| d.as_str())
+                .is_some_and(|bin| {
+                    !targets
+                        .iter()
+                        .any(|target| target.kind == TargetKind::Bin && target.name == bin)
+                });
+            if is_dangling {
+                package.remove("default-run");
+            }
+        }
+    }
+}
+
+/// If the top-level `Cargo.toml` has a `[workspace]` section, set its `members` field to
 /// a list consisting of just the path to the package.
 ///
 /// Also deletes the `default-members` field because it does not play nicely
@@ -322,36 +1491,50 @@ fn extract_cargo_metadata(path: &Path) -> Result<cargo_metadata::Metadata, anyho
 fn ignore_all_members_except(
     manifests: &mut [ParsedManifest],
     metadata: &Metadata,
-    member: String,
-) {
+    workspace_manifest: &Path,
+    member: &str,
+) -> Result<(), anyhow::Error> {
     let workspace_toml = manifests
         .iter_mut()
-        .find(|manifest| manifest.relative_path == std::path::PathBuf::from("Cargo.toml"));
+        .find(|manifest| manifest.relative_path == workspace_manifest);
 
-    if let Some(workspace) = workspace_toml.and_then(|toml| toml.contents.get_mut("workspace")) {
-        if let Some(members) = workspace.get_mut("members") {
-            let workspace_root = &metadata.workspace_root;
-            let workspace_packages = metadata.workspace_packages();
-
-            if let Some(pkg) = workspace_packages
-                .into_iter()
-                .find(|pkg| pkg.name == member)
-            {
-                // Make this a relative path to the workspace, and remove the `Cargo.toml` child.
-                let member_cargo_path = diff_paths(pkg.manifest_path.as_os_str(), workspace_root);
-                let member_workspace_path = member_cargo_path
-                    .as_ref()
-                    .and_then(|path| path.parent())
-                    .and_then(|dir| dir.to_str());
+    if let Some(workspace) = workspace_toml
+        .and_then(|toml| toml.contents.get_mut("workspace"))
+        .and_then(|workspace| workspace.as_table_mut())
+    {
+        let workspace_root = &metadata.workspace_root;
+        let workspace_packages = metadata.workspace_packages();
 
-                if let Some(member_path) = member_workspace_path {
-                    *members =
-                        toml::Value::Array(vec![toml::Value::String(member_path.to_string())]);
-                }
-            }
-        }
-        if let Some(workspace) = workspace.as_table_mut() {
-            workspace.remove("default-members");
+        if let Some(pkg) = workspace_packages
+            .into_iter()
+            .find(|pkg| pkg.name == *member)
+        {
+            // Make this a relative path to the workspace, and remove the `Cargo.toml` child.
+            let member_cargo_path = diff_paths(pkg.manifest_path.as_os_str(), workspace_root);
+            let member_workspace_path = member_cargo_path
+                .as_ref()
+                .and_then(|path| path.parent())
+                .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Failed to compute the path of `{}` relative to the workspace root.",
+                    member
+                )
+            })?;
+            let member_path = member_workspace_path.to_str().ok_or_else(|| {
+                anyhow::anyhow!(
+                    "The path of `{}`, {:?}, is not valid UTF-8.",
+                    member,
+                    member_workspace_path
+                )
+            })?;
+            // If `members` is omitted, all path dependencies of the root package are implicit
+            // members: we list the selected member explicitly to prune the other ones.
+            workspace.insert(
+                "members".to_string(),
+                toml::Value::Array(vec![toml::Value::String(member_path.to_string())]),
+            );
         }
+        workspace.remove("default-members");
     }
+    Ok(())
 }
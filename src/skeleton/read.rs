@@ -1,12 +1,13 @@
 //! Logic to read all the files required to build a caching layer for a project.
-use super::ParsedManifest;
+use super::{parse_manifest, restore_editions, ExtraFile, Manifest, ParsedManifest};
 use crate::skeleton::target::{Target, TargetKind};
 use crate::RustToolchainFile;
+use anyhow::Context;
 use cargo_metadata::{Metadata, Package};
+use globwalk::GlobWalkerBuilder;
 use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
 
 pub(super) fn config<P: AsRef<Path>>(base_path: &P) -> Result<Option<String>, anyhow::Error> {
     // Given that we run primarily in Docker, assume to find config or config.toml at root level.
@@ -59,18 +60,42 @@ pub(super) fn manifests<P: AsRef<Path>>(
         // However, if this root manifest doesn't contain [package], it is not considered a package
         // by cargo metadata. Therefore, we have to add it manually.
         // Workspaces currently cannot be nested, so this should only happen at the root.
-        packages.insert(base_path.as_ref().join("Cargo.toml"), Default::default());
+        packages.insert(
+            metadata
+                .workspace_root
+                .join("Cargo.toml")
+                .into_std_path_buf(),
+            Default::default(),
+        );
     }
 
     let mut manifests = vec![];
     for (absolute_path, targets) in packages {
         let contents = fs::read_to_string(&absolute_path)?;
 
-        let mut parsed = cargo_manifest::Manifest::from_str(&contents)?;
+        let (mut parsed, editions) = parse_manifest(&contents)?;
         // Required to detect bin/libs when the related section is omitted from the manifest
         parsed.complete_from_path(&absolute_path)?;
+        // `cargo_manifest` doesn't know about `autolib`: it discovers `src/lib.rs` regardless.
+        let raw: toml::Value = toml::from_str(&contents)?;
+        let autolib = raw
+            .get("package")
+            .and_then(|package| package.get("autolib"))
+            .and_then(|autolib| autolib.as_bool());
+        if autolib == Some(false) && raw.get("lib").is_none() {
+            parsed.lib = None;
+        }
 
         let mut intermediate = toml::Value::try_from(parsed)?;
+        restore_editions(&mut intermediate, &editions);
+        if let (Some(autolib), Some(package)) = (
+            autolib,
+            intermediate
+                .get_mut("package")
+                .and_then(|package| package.as_table_mut()),
+        ) {
+            package.insert("autolib".to_owned(), toml::Value::Boolean(autolib));
+        }
 
         // Specifically, toml gives no guarantees to the ordering of the auto binaries
         // in its results. We will manually sort these to ensure that the output
@@ -79,19 +104,22 @@ pub(super) fn manifests<P: AsRef<Path>>(
             .get_mut("bin")
             .and_then(|bins| bins.as_array_mut());
         if let Some(bins) = bins {
-            bins.sort_by(|bin_a, bin_b| {
-                let bin_a_path = bin_a
-                    .as_table()
-                    .and_then(|table| table.get("path").or_else(|| table.get("name")))
-                    .and_then(|path| path.as_str())
-                    .unwrap();
-                let bin_b_path = bin_b
-                    .as_table()
-                    .and_then(|table| table.get("path").or_else(|| table.get("name")))
+            // Multiple binaries can share the same path: we fall back to their names to break ties.
+            let sort_key = |bin: &toml::Value| {
+                let table = bin.as_table();
+                let name = table
+                    .and_then(|table| table.get("name"))
+                    .and_then(|name| name.as_str())
+                    .map(|name| name.to_owned());
+                let path = table
+                    .and_then(|table| table.get("path"))
                     .and_then(|path| path.as_str())
+                    .map(|path| path.to_owned())
+                    .or_else(|| name.clone())
                     .unwrap();
-                bin_a_path.cmp(bin_b_path)
-            });
+                (path, name)
+            };
+            bins.sort_by_key(sort_key);
         }
 
         let relative_path = pathdiff::diff_paths(&absolute_path, base_path).ok_or_else(|| {
@@ -111,6 +139,107 @@ pub(super) fn manifests<P: AsRef<Path>>(
     Ok(manifests)
 }
 
+/// Read the files in `paths`, relative to `base_path`, skipping the ones that do not exist.
+pub(super) fn tool_config_files<P: AsRef<Path>, S: AsRef<str>>(
+    base_path: &P,
+    paths: &[S],
+) -> Result<Vec<ExtraFile>, anyhow::Error> {
+    let mut extra_files = vec![];
+    for path in paths {
+        let relative_path = PathBuf::from(path.as_ref());
+        match fs::read_to_string(base_path.as_ref().join(&relative_path)) {
+            Ok(contents) => extra_files.push(ExtraFile {
+                relative_path,
+                contents,
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(anyhow::Error::from(e)
+                    .context(format!("Failed to read {}.", relative_path.display())))
+            }
+        }
+    }
+    Ok(extra_files)
+}
+
+/// Build dependencies generating code out of files that are not manifests (e.g. `tonic-build`
+/// compiling `.proto` files).
+const CODEGEN_BUILD_DEPENDENCIES: &[&str] = &[
+    "tonic-build",
+    "prost-build",
+    "protobuf-codegen",
+    "protoc-rust",
+];
+
+/// Capture the files matching `patterns`, relative to the manifest directory, for all crates
+/// that have one of the [`CODEGEN_BUILD_DEPENDENCIES`] among their build dependencies.
+pub(super) fn build_inputs<P: AsRef<Path>>(
+    base_path: &P,
+    manifests: &[ParsedManifest],
+    patterns: &[String],
+) -> Result<Vec<ExtraFile>, anyhow::Error> {
+    let mut extra_files = vec![];
+    for manifest in manifests {
+        let has_codegen_build_dependency = manifest
+            .contents
+            .get("build-dependencies")
+            .and_then(|dependencies| dependencies.as_table())
+            .map(|dependencies| {
+                dependencies.iter().any(|(name, dependency)| {
+                    // Dependencies can be renamed using the `package` key.
+                    let package = dependency
+                        .get("package")
+                        .and_then(|package| package.as_str())
+                        .unwrap_or(name);
+                    CODEGEN_BUILD_DEPENDENCIES.contains(&package)
+                })
+            })
+            .unwrap_or(false);
+        if !has_codegen_build_dependency {
+            continue;
+        }
+
+        let manifest_directory = base_path
+            .as_ref()
+            .join(&manifest.relative_path)
+            .parent()
+            .unwrap()
+            .to_path_buf();
+        let patterns: Vec<&str> = patterns
+            .iter()
+            .map(|pattern| pattern.as_str())
+            .chain(PRUNED_DIRECTORIES.iter().copied())
+            .collect();
+        let walker =
+            globwalk::GlobWalkerBuilder::from_patterns(&manifest_directory, &patterns).build()?;
+        for file in walker {
+            let file = file?;
+            if !file.file_type().is_file() {
+                continue;
+            }
+            let relative_path = pathdiff::diff_paths(file.path(), base_path).ok_or_else(|| {
+                anyhow::anyhow!("Failed to compute relative path of {:?}", file.path())
+            })?;
+            let contents = fs::read(file.path())
+                .with_context(|| format!("Failed to read {}.", file.path().display()))?;
+            match String::from_utf8(contents) {
+                Ok(contents) => extra_files.push(ExtraFile {
+                    relative_path,
+                    contents,
+                }),
+                // Recipes are JSON documents: binary files (e.g. descriptor sets) can't be embedded.
+                Err(_) => eprintln!(
+                    "WARNING `{}` is not valid UTF-8: it is left out of the recipe.",
+                    relative_path.display()
+                ),
+            }
+        }
+    }
+    // Walking the filesystem does not guarantee any ordering.
+    extra_files.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+    Ok(extra_files)
+}
+
 fn gather_targets(package: &Package) -> BTreeSet<Target> {
     let manifest_path = package.manifest_path.clone().into_std_path_buf();
     let root_dir = manifest_path.parent().unwrap();
@@ -149,6 +278,50 @@ fn gather_targets(package: &Package) -> BTreeSet<Target> {
         .collect()
 }
 
+/// Exclusion patterns for the directories that are never worth scanning for files to capture,
+/// while potentially huge: build artifacts, version control data and JavaScript dependencies.
+/// The walker does not descend into them at all.
+const PRUNED_DIRECTORIES: &[&str] = &["!target", "!.git", "!node_modules"];
+
+/// The directories, relative to `base_path`, of the independent projects nested inside the
+/// project: the ones with their own `Cargo.lock` that are not part of the workspace.
+pub(super) fn nested_projects<P: AsRef<Path>>(
+    base_path: &P,
+    manifests: &[Manifest],
+) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let base_path = base_path.as_ref();
+    let patterns: Vec<&str> = std::iter::once("**/Cargo.lock")
+        .chain(PRUNED_DIRECTORIES.iter().copied())
+        .collect();
+    let walker = GlobWalkerBuilder::from_patterns(base_path, &patterns)
+        .build()
+        .context("Failed to scan the project for nested lock files.")?;
+    let mut directories = vec![];
+    for entry in walker {
+        let entry = entry.context("Failed to scan the project for nested lock files.")?;
+        let directory = match entry
+            .path()
+            .parent()
+            .and_then(|parent| parent.strip_prefix(base_path).ok())
+        {
+            Some(directory) => directory.to_path_buf(),
+            None => continue,
+        };
+        let is_captured = manifests
+            .iter()
+            .any(|manifest| manifest.relative_path.parent() == Some(directory.as_path()));
+        if directory.as_os_str().is_empty()
+            || is_captured
+            || !base_path.join(&directory).join("Cargo.toml").is_file()
+        {
+            continue;
+        }
+        directories.push(directory);
+    }
+    directories.sort();
+    Ok(directories)
+}
+
 pub(super) fn lockfile<P: AsRef<Path>>(
     base_path: &P,
 ) -> Result<Option<toml::Value>, anyhow::Error> {
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
 pub enum TargetKind {
@@ -16,3 +16,18 @@ pub struct Target {
     pub(crate) kind: TargetKind,
     pub(crate) name: String,
 }
+
+impl Target {
+    /// Path of the target entrypoint, relative to the directory of its manifest.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn kind(&self) -> &TargetKind {
+        &self.kind
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
@@ -68,43 +68,47 @@ fn mask_local_dependency_versions(
     local_package_names: &[toml::Value],
     manifest: &mut ParsedManifest,
 ) {
-    fn _mask(local_package_names: &[toml::Value], toml_value: &mut toml::Value) {
-        for dependency_key in ["dependencies", "dev-dependencies", "build-dependencies"] {
-            if let Some(dependencies) = toml_value.get_mut(dependency_key) {
-                if let Some(dependencies) = dependencies.as_table_mut() {
-                    for (key, dependency) in dependencies {
-                        if dependency.get("path").is_none() {
-                            // This dependency is not local
-                            continue;
-                        }
+    fn _mask_dependencies(local_package_names: &[toml::Value], dependencies: &mut toml::Value) {
+        if let Some(dependencies) = dependencies.as_table_mut() {
+            for (key, dependency) in dependencies {
+                if dependency.get("path").is_none() {
+                    // This dependency is not local
+                    continue;
+                }
 
-                        let mut must_mark_version = false;
+                let mut must_mark_version = false;
 
-                        if let Some(package_name) = dependency.get("package") {
-                            // We are dealing with a renamed package, so we check the name of the
-                            // "source" package.
-                            if local_package_names.contains(package_name) {
-                                must_mark_version = true;
-                            }
-                        } else {
-                            // The package has not been renamed, so we check the name of the
-                            // key in the dependencies table.
-                            if local_package_names.contains(&toml::Value::String(key.to_string())) {
-                                must_mark_version = true;
-                            }
-                        }
+                if let Some(package_name) = dependency.get("package") {
+                    // We are dealing with a renamed package, so we check the name of the
+                    // "source" package.
+                    if local_package_names.contains(package_name) {
+                        must_mark_version = true;
+                    }
+                } else {
+                    // The package has not been renamed, so we check the name of the
+                    // key in the dependencies table.
+                    if local_package_names.contains(&toml::Value::String(key.to_string())) {
+                        must_mark_version = true;
+                    }
+                }
 
-                        if must_mark_version {
-                            if let Some(version) = dependency.get_mut("version") {
-                                *version = toml::Value::String(CONST_VERSION.to_string());
-                            }
-                        }
+                if must_mark_version {
+                    if let Some(version) = dependency.get_mut("version") {
+                        *version = toml::Value::String(CONST_VERSION.to_string());
                     }
                 }
             }
         }
     }
 
+    fn _mask(local_package_names: &[toml::Value], toml_value: &mut toml::Value) {
+        for dependency_key in ["dependencies", "dev-dependencies", "build-dependencies"] {
+            if let Some(dependencies) = toml_value.get_mut(dependency_key) {
+                _mask_dependencies(local_package_names, dependencies);
+            }
+        }
+    }
+
     // There are three ways to specify dependencies:
     // - top-level
     // ```toml
@@ -147,6 +151,20 @@ fn mask_local_dependency_versions(
         // Mask the local crates in the workspace dependencies
         _mask(local_package_names, workspace);
     }
+
+    // Local crates can also be used to patch a dependency, in which case the version
+    // requirement must match the masked version of the local crate.
+    // ```toml
+    // [patch.crates-io]
+    // project_a = { path = "project_a", version = "0.2.0" }
+    // ```
+    if let Some(patch) = manifest.contents.get_mut("patch") {
+        if let Some(registries) = patch.as_table_mut() {
+            for (_, patches) in registries.iter_mut() {
+                _mask_dependencies(local_package_names, patches);
+            }
+        }
+    }
 }
 
 fn parse_local_crate_names(manifests: &[ParsedManifest]) -> Vec<toml::Value> {
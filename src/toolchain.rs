@@ -0,0 +1,143 @@
+//! Logic to install the targets and components required by the project's rust toolchain file.
+use crate::RustToolchainFile;
+use anyhow::Context;
+use std::path::Path;
+use std::process::Command;
+
+/// The targets and components listed in a `rust-toolchain.toml` file.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ToolchainRequirements {
+    pub targets: Vec<String>,
+    pub components: Vec<String>,
+}
+
+impl ToolchainRequirements {
+    /// Extract targets and components from the contents of a rust toolchain file.
+    ///
+    /// Legacy `rust-toolchain` files can only specify a channel, therefore they never have
+    /// any requirement.
+    pub fn parse(file_kind: &RustToolchainFile, contents: &str) -> Result<Self, anyhow::Error> {
+        if let RustToolchainFile::Bare = file_kind {
+            return Ok(Self::default());
+        }
+        let parsed: toml::Value =
+            toml::from_str(contents).context("Failed to parse rust-toolchain.toml file.")?;
+        let string_array = |key: &str| -> Vec<String> {
+            parsed
+                .get("toolchain")
+                .and_then(|toolchain| toolchain.get(key))
+                .and_then(|value| value.as_array())
+                .map(|values| {
+                    values
+                        .iter()
+                        .filter_map(|value| value.as_str())
+                        .map(|value| value.to_string())
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        Ok(Self {
+            targets: string_array("targets"),
+            components: string_array("components"),
+        })
+    }
+
+    /// The `rustup` invocations required to install all targets and components.
+    ///
+    /// They must be executed from the directory containing the toolchain file, in order for
+    /// `rustup` to pick the right toolchain.
+    pub fn rustup_commands(&self) -> Vec<Command> {
+        let mut commands = vec![];
+        if !self.targets.is_empty() {
+            let mut command = Command::new("rustup");
+            command.args(["target", "add"]).args(&self.targets);
+            commands.push(command);
+        }
+        if !self.components.is_empty() {
+            let mut command = Command::new("rustup");
+            command.args(["component", "add"]).args(&self.components);
+            commands.push(command);
+        }
+        commands
+    }
+
+    /// Run all `rustup` invocations from `base_path`.
+    ///
+    /// If `rustup` is not installed we print a warning and carry on: the toolchain might have
+    /// been provisioned in a different way (e.g. a distro package).
+    pub(crate) fn install(&self, base_path: &Path) -> Result<(), anyhow::Error> {
+        for mut command in self.rustup_commands() {
+            let status = match command.current_dir(base_path).status() {
+                Ok(status) => status,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    eprintln!(
+                        "WARNING `rustup` is not available: skipping the installation of the \
+                        targets and components listed in the rust toolchain file."
+                    );
+                    return Ok(());
+                }
+                Err(e) => return Err(anyhow::Error::from(e).context("Failed to invoke `rustup`.")),
+            };
+            if !status.success() {
+                anyhow::bail!("`{:?}` failed with {}", command, status);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The targets in `requested` that are not listed in the output of
+/// `rustup target list --installed`.
+///
+/// Paths to custom target specifications (i.e. `.json` files) are never reported as missing:
+/// `rustup` does not manage them.
+pub fn missing_targets(installed: &str, requested: &[String]) -> Vec<String> {
+    let installed: Vec<&str> = installed.lines().map(str::trim).collect();
+    requested
+        .iter()
+        .filter(|target| !target.ends_with(".json") && !installed.contains(&target.as_str()))
+        .cloned()
+        .collect()
+}
+
+/// Make sure that the targets passed via `--target` are installed before invoking `cargo`,
+/// which otherwise fails with a terse "the target may not be installed".
+///
+/// The missing targets are installed via `rustup` if `install` is set, otherwise we print a hint.
+/// Nothing is checked if `rustup` is not available or fails to list the installed targets.
+pub(crate) fn check_targets(
+    requested: &[String],
+    base_path: &Path,
+    install: bool,
+) -> Result<(), anyhow::Error> {
+    let output = match Command::new("rustup")
+        .args(["target", "list", "--installed"])
+        .current_dir(base_path)
+        .output()
+    {
+        Ok(output) if output.status.success() => output,
+        _ => return Ok(()),
+    };
+    let missing = missing_targets(&String::from_utf8_lossy(&output.stdout), requested);
+    if missing.is_empty() {
+        return Ok(());
+    }
+    if install {
+        let requirements = ToolchainRequirements {
+            targets: missing,
+            components: vec![],
+        };
+        return requirements
+            .install(base_path)
+            .context("Failed to install the targets passed via `--target`.");
+    }
+    for target in missing {
+        eprintln!(
+            "WARNING The `{}` target is not installed in the active toolchain: the build is \
+            likely to fail. Run `rustup target add {}` or pass `--install-targets` to install \
+            it automatically.",
+            target, target
+        );
+    }
+    Ok(())
+}
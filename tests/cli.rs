@@ -0,0 +1,1942 @@
+use assert_cmd::Command;
+use assert_fs::prelude::*;
+use assert_fs::TempDir;
+use predicates::prelude::*;
+
+fn cargo_chef(directory: &TempDir) -> Command {
+    let mut command = Command::cargo_bin("cargo-chef").unwrap();
+    command.current_dir(directory.path()).arg("chef");
+    command
+}
+
+fn workspace() -> TempDir {
+    let directory = TempDir::new().unwrap();
+    directory
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = ["project_a", "project_b"]
+"#,
+        )
+        .unwrap();
+    directory
+        .child("project_a")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "project_a"
+version = "0.1.0"
+edition = "2018"
+"#,
+        )
+        .unwrap();
+    directory
+        .child("project_a")
+        .child("src")
+        .child("main.rs")
+        .touch()
+        .unwrap();
+    directory
+        .child("project_b")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "project_b"
+version = "0.1.0"
+edition = "2018"
+"#,
+        )
+        .unwrap();
+    directory
+        .child("project_b")
+        .child("src")
+        .child("lib.rs")
+        .touch()
+        .unwrap();
+    directory
+}
+
+#[test]
+fn print_skeleton_lists_manifests_without_writing_a_recipe() {
+    // Arrange
+    let project = workspace();
+
+    // Act
+    let assert = cargo_chef(&project)
+        .args(["prepare", "--print-skeleton"])
+        .assert();
+
+    // Assert
+    assert.success().stderr(
+        predicate::str::contains("  Cargo.toml\n")
+            .and(predicate::str::contains("project_a/Cargo.toml"))
+            .and(predicate::str::contains("bin `project_a` at src/main.rs"))
+            .and(predicate::str::contains("project_b/Cargo.toml"))
+            .and(predicate::str::contains("lib `project_b` at src/lib.rs"))
+            .and(predicate::str::contains("lock file: absent")),
+    );
+    project
+        .child("recipe.json")
+        .assert(predicate::path::missing());
+}
+
+/// A stand-in for `cargo` that records the arguments of every invocation, one per line.
+/// Just like `cargo`, it creates the target directory that `cook` is going to clean up.
+/// `cargo metadata` invocations are delegated to the real `cargo`.
+#[cfg(unix)]
+struct FakeCargo {
+    directory: TempDir,
+}
+
+#[cfg(unix)]
+impl FakeCargo {
+    fn new() -> Self {
+        Self::with_body(&format!(
+            "if [ \"$1\" = metadata ]; then exec \"{}\" \"$@\"; fi\nmkdir -p target/debug target/release",
+            env!("CARGO")
+        ))
+    }
+
+    /// Record the arguments and then delegate to the real `cargo`, for commands whose output
+    /// has to be parsed (e.g. `cargo metadata`).
+    fn wrapping_cargo() -> Self {
+        Self::with_body(&format!("exec \"{}\" \"$@\"", env!("CARGO")))
+    }
+
+    fn with_body(body: &str) -> Self {
+        use std::os::unix::fs::PermissionsExt;
+
+        let directory = TempDir::new().unwrap();
+        let script = directory.child("cargo");
+        script
+            .write_str(&format!(
+                "#!/bin/sh\necho \"$@\" >> \"{}\"\n{}\n",
+                directory.child("invocations.log").path().display(),
+                body
+            ))
+            .unwrap();
+        std::fs::set_permissions(script.path(), std::fs::Permissions::from_mode(0o755)).unwrap();
+        Self { directory }
+    }
+
+    fn path(&self) -> std::path::PathBuf {
+        self.directory.child("cargo").path().to_path_buf()
+    }
+
+    fn invocations(&self) -> Vec<String> {
+        std::fs::read_to_string(self.directory.child("invocations.log").path())
+            .unwrap_or_default()
+            .lines()
+            .map(|line| line.to_string())
+            .collect()
+    }
+}
+
+/// Run `cargo chef prepare` on `project` and return the path to the generated recipe.
+fn prepare(project: &TempDir) -> std::path::PathBuf {
+    cargo_chef(project).arg("prepare").assert().success();
+    project.child("recipe.json").path().to_path_buf()
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_builds_once_per_feature_set() {
+    // Arrange
+    let project = workspace();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+    let cargo = FakeCargo::new();
+
+    // Act
+    cargo_chef(&cook_directory)
+        .env("CARGO", cargo.path())
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .args(["--feature-set", "a", "--feature-set", "b"])
+        .assert()
+        .success();
+
+    // Assert
+    assert_eq!(
+        cargo.invocations(),
+        vec!["build --features a", "build --features b"]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_forwards_host_config_flags() {
+    // Arrange
+    let project = workspace();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+    let cargo = FakeCargo::new();
+
+    // Act
+    cargo_chef(&cook_directory)
+        .env("CARGO", cargo.path())
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .args(["--target", "x86_64-unknown-linux-gnu"])
+        .args(["-Z", "target-applies-to-host", "-Z", "host-config"])
+        .assert()
+        .success();
+
+    // Assert
+    let invocations = cargo.invocations();
+    assert_eq!(invocations.len(), 1);
+    for flag in [
+        "-Z target-applies-to-host",
+        "-Z host-config",
+        "--target x86_64-unknown-linux-gnu",
+    ] {
+        assert!(invocations[0].contains(flag), "{}", invocations[0]);
+    }
+}
+
+#[test]
+fn cook_rejects_host_config_without_target_applies_to_host() {
+    // Arrange
+    let project = workspace();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+
+    // Act
+    let assert = cargo_chef(&cook_directory)
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .args(["--target", "x86_64-unknown-linux-gnu", "-Z", "host-config"])
+        .assert();
+
+    // Assert
+    assert.failure().stderr(predicate::str::contains(
+        "`-Z host-config` requires `-Z target-applies-to-host`",
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_skips_if_unchanged() {
+    // Arrange
+    let project = workspace();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+    let cargo = FakeCargo::new();
+    let cook = || {
+        cargo_chef(&cook_directory)
+            .env("CARGO", cargo.path())
+            .arg("cook")
+            .arg("--recipe-path")
+            .arg(&recipe_path)
+            .arg("--skip-if-unchanged")
+            .assert()
+            .success()
+    };
+
+    // Act
+    cook();
+    let second_cook = cook();
+
+    // Assert
+    second_cook.stderr(predicate::str::contains(
+        "Dependencies have already been cooked for this recipe.",
+    ));
+    assert_eq!(cargo.invocations(), vec!["build"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_does_not_skip_the_cleanup_left_out_by_a_previous_cook() {
+    // Arrange
+    let project = workspace();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+    let cargo = FakeCargo::with_body(
+        "mkdir -p target/debug/deps
+        touch target/debug/deps/libproject_b-0123456789abcdef.rlib",
+    );
+    let cook = |args: &[&str]| {
+        cargo_chef(&cook_directory)
+            .env("CARGO", cargo.path())
+            .arg("cook")
+            .arg("--recipe-path")
+            .arg(&recipe_path)
+            .arg("--skip-if-unchanged")
+            .args(args)
+            .assert()
+            .success()
+    };
+
+    // Act
+    cook(&["--no-cleanup"]);
+    let second_cook = cook(&[]);
+
+    // Assert
+    second_cook.stderr(predicate::str::contains("already been cooked").not());
+    assert_eq!(cargo.invocations(), vec!["build", "build"]);
+    cook_directory
+        .child("target/debug/deps/libproject_b-0123456789abcdef.rlib")
+        .assert(predicate::path::missing());
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_is_not_skipped_when_rustflags_change() {
+    // Arrange
+    let project = workspace();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+    let cargo = FakeCargo::new();
+    let cook = |rustflags: &str| {
+        cargo_chef(&cook_directory)
+            .env("CARGO", cargo.path())
+            .env("RUSTFLAGS", rustflags)
+            .env_remove("CARGO_ENCODED_RUSTFLAGS")
+            .arg("cook")
+            .arg("--recipe-path")
+            .arg(&recipe_path)
+            .arg("--skip-if-unchanged")
+            .assert()
+            .success()
+    };
+
+    // Act
+    cook("");
+    let second_cook = cook("--cfg chef");
+
+    // Assert
+    second_cook.stderr(predicate::str::contains("already been cooked").not());
+    assert_eq!(cargo.invocations(), vec!["build", "build"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_tracks_each_profile_separately_when_skipping() {
+    // Arrange
+    let project = workspace();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+    let cargo = FakeCargo::new();
+    let cook = |args: &[&str]| {
+        cargo_chef(&cook_directory)
+            .env("CARGO", cargo.path())
+            .arg("cook")
+            .arg("--recipe-path")
+            .arg(&recipe_path)
+            .arg("--skip-if-unchanged")
+            .args(args)
+            .assert()
+            .success()
+    };
+
+    // Act
+    cook(&[]);
+    let release_cook = cook(&["--release"]);
+    let debug_cook = cook(&[]);
+
+    // Assert
+    release_cook.stderr(predicate::str::contains("already been cooked").not());
+    debug_cook.stderr(predicate::str::contains(
+        "Dependencies have already been cooked for this recipe.",
+    ));
+    assert_eq!(cargo.invocations(), vec!["build", "build --release"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_logs_the_duration_of_each_phase() {
+    // Arrange
+    let project = workspace();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+    let cargo = FakeCargo::new();
+
+    // Act
+    let assert = cargo_chef(&cook_directory)
+        .env("CARGO", cargo.path())
+        .env("RUST_LOG", "info")
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .assert();
+
+    // Assert
+    assert.success().stderr(
+        predicate::str::contains("Rehydrating the skeleton took")
+            .and(predicate::str::contains("Building the dependencies took"))
+            .and(predicate::str::contains("Cleaning up dummy artifacts took")),
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_does_not_log_timings_by_default() {
+    // Arrange
+    let project = workspace();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+    let cargo = FakeCargo::new();
+
+    // Act
+    let assert = cargo_chef(&cook_directory)
+        .env("CARGO", cargo.path())
+        .env_remove("RUST_LOG")
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .assert();
+
+    // Assert
+    assert
+        .success()
+        .stderr(predicate::str::contains(" took ").not());
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_expands_target_aliases() {
+    // Arrange
+    let project = workspace();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+    let cargo = FakeCargo::new();
+
+    // Act
+    cargo_chef(&cook_directory)
+        .env("CARGO", cargo.path())
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .args(["--target", "musl", "--target", "aarch64-unknown-linux-gnu"])
+        .assert()
+        .success();
+
+    // Assert
+    assert_eq!(
+        cargo.invocations(),
+        vec![format!(
+            "build --target {}-unknown-linux-musl --target aarch64-unknown-linux-gnu",
+            std::env::consts::ARCH
+        )]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn prepare_forwards_metadata_args() {
+    // Arrange
+    let project = workspace();
+    let cargo = FakeCargo::wrapping_cargo();
+
+    // Act
+    cargo_chef(&project)
+        .env("CARGO", cargo.path())
+        .arg("prepare")
+        .args([
+            "--metadata-arg",
+            "--filter-platform",
+            "--metadata-arg",
+            "x86_64-unknown-linux-gnu",
+        ])
+        .assert()
+        .success();
+
+    // Assert
+    let invocations = cargo.invocations();
+    assert_eq!(invocations.len(), 1);
+    assert!(
+        invocations[0].starts_with("metadata")
+            && invocations[0].contains("--filter-platform x86_64-unknown-linux-gnu"),
+        "{}",
+        invocations[0]
+    );
+}
+
+#[test]
+fn prepare_rejects_reserved_metadata_args() {
+    // Arrange
+    let project = workspace();
+
+    // Act
+    let assert = cargo_chef(&project)
+        .arg("prepare")
+        .arg("--metadata-arg=--no-deps")
+        .assert();
+
+    // Assert
+    assert.failure().stderr(predicate::str::contains(
+        "`--no-deps` is always passed to `cargo metadata`",
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_forwards_config_overrides() {
+    // Arrange
+    let project = workspace();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+    cook_directory
+        .child("extra.toml")
+        .write_str("[build]\njobs = 2\n")
+        .unwrap();
+    let cargo = FakeCargo::new();
+
+    // Act
+    cargo_chef(&cook_directory)
+        .env("CARGO", cargo.path())
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .args([
+            "--config",
+            "extra.toml",
+            "--config",
+            "net.git-fetch-with-cli=true",
+        ])
+        .assert()
+        .success();
+
+    // Assert
+    assert_eq!(
+        cargo.invocations(),
+        vec!["build --config extra.toml --config net.git-fetch-with-cli=true"]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_forwards_robust_network_settings() {
+    // Arrange
+    let project = workspace();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+    let cargo = FakeCargo::new();
+
+    // Act
+    cargo_chef(&cook_directory)
+        .env("CARGO", cargo.path())
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .args(["--robust-network", "--config", "net.retry=5"])
+        .assert()
+        .success();
+
+    // Assert
+    assert_eq!(
+        cargo.invocations(),
+        vec![
+            "build --config net.retry=3 --config net.git-fetch-with-cli=true --config net.retry=5"
+        ]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_explains_the_cargo_command() {
+    // Arrange
+    let project = workspace();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+    let cargo = FakeCargo::new();
+
+    // Act
+    let cook = cargo_chef(&cook_directory)
+        .env("CARGO", cargo.path())
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .args(["--release", "--jobs", "4", "--explain"])
+        .assert();
+
+    // Assert
+    cook.success().stderr(
+        predicate::str::contains("Running `cargo build --release --jobs 4`:")
+            .and(predicate::str::contains("  build: the default command"))
+            .and(predicate::str::contains(
+                "  --release: `--release` (or `--profile release`) was passed",
+            ))
+            .and(predicate::str::contains(
+                "  --jobs 4: `--jobs` (or `--auto-jobs`) was passed",
+            )),
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_forwards_repeated_verbose_flags() {
+    // Arrange
+    let project = workspace();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+    let cargo = FakeCargo::new();
+
+    // Act
+    cargo_chef(&cook_directory)
+        .env("CARGO", cargo.path())
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .arg("-vv")
+        .assert()
+        .success();
+
+    // Assert
+    assert_eq!(cargo.invocations(), vec!["build --verbose --verbose"]);
+}
+
+#[test]
+fn cook_rejects_missing_config_files() {
+    // Arrange
+    let project = workspace();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+
+    // Act
+    let assert = cargo_chef(&cook_directory)
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .args(["--config", "missing.toml"])
+        .assert();
+
+    // Assert
+    assert.failure().stderr(predicate::str::contains(
+        "`--config missing.toml` is neither a `KEY=VALUE` assignment",
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_forwards_examples() {
+    // Arrange
+    let project = workspace();
+    for example in ["one", "two", "three"] {
+        project
+            .child("project_b")
+            .child("examples")
+            .child(format!("{}.rs", example))
+            .write_str("fn main() {}")
+            .unwrap();
+    }
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+    let cargo = FakeCargo::new();
+
+    // Act
+    cargo_chef(&cook_directory)
+        .env("CARGO", cargo.path())
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .args(["--example", "one", "--example", "two"])
+        .assert()
+        .success();
+
+    // Assert
+    assert_eq!(
+        cargo.invocations(),
+        vec!["build --example one --example two"]
+    );
+    let examples = cook_directory.child("project_b").child("examples");
+    examples.child("one.rs").assert(predicate::path::exists());
+    examples.child("two.rs").assert(predicate::path::exists());
+    examples
+        .child("three.rs")
+        .assert(predicate::path::missing());
+}
+
+#[test]
+fn cook_rejects_unknown_examples() {
+    // Arrange
+    let project = workspace();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+
+    // Act
+    let assert = cargo_chef(&cook_directory)
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .args(["--example", "missing"])
+        .assert();
+
+    // Assert
+    assert.failure().stderr(predicate::str::contains(
+        "There is no example named `missing` in the recipe.",
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_can_prepare_the_recipe_in_memory() {
+    // Arrange
+    let project = workspace();
+    let cook_directory = TempDir::new().unwrap();
+    let cargo = FakeCargo::new();
+
+    // Act
+    cargo_chef(&cook_directory)
+        .env("CARGO", cargo.path())
+        .arg("cook")
+        .arg("--prepare-from")
+        .arg(project.path())
+        .assert()
+        .success();
+
+    // Assert
+    let invocations = cargo.invocations();
+    assert!(invocations[0].starts_with("metadata"), "{}", invocations[0]);
+    assert_eq!(invocations[1..], ["build"]);
+    cook_directory
+        .child("project_a")
+        .child("src")
+        .child("main.rs")
+        .assert("fn main() {}");
+    cook_directory
+        .child("project_b")
+        .child("Cargo.toml")
+        .assert(predicate::path::exists());
+    project
+        .child("recipe.json")
+        .assert(predicate::path::missing());
+    cook_directory
+        .child("recipe.json")
+        .assert(predicate::path::missing());
+}
+
+#[test]
+fn cook_refuses_to_prepare_in_memory_over_the_project() {
+    // Arrange
+    let project = workspace();
+
+    // Act
+    let assert = cargo_chef(&project)
+        .arg("cook")
+        .arg("--prepare-from")
+        .arg(".")
+        .assert();
+
+    // Assert
+    assert.failure().stderr(predicate::str::contains(
+        "`--prepare-from` would overwrite the source files of the project",
+    ));
+    project
+        .child("project_a")
+        .child("src")
+        .child("main.rs")
+        .assert("");
+}
+
+#[test]
+fn recipe_path_defaults_to_the_env_var() {
+    // Arrange
+    let project = workspace();
+
+    // Act
+    cargo_chef(&project)
+        .env("CARGO_CHEF_RECIPE_PATH", "from-env.json")
+        .arg("prepare")
+        .assert()
+        .success();
+    cargo_chef(&project)
+        .env("CARGO_CHEF_RECIPE_PATH", "from-env.json")
+        .args(["prepare", "--recipe-path", "from-flag.json"])
+        .assert()
+        .success();
+
+    // Assert
+    project
+        .child("from-env.json")
+        .assert(predicate::path::exists());
+    project
+        .child("from-flag.json")
+        .assert(predicate::path::exists());
+    project
+        .child("recipe.json")
+        .assert(predicate::path::missing());
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_can_prepare_in_memory_when_the_recipe_path_env_var_is_set() {
+    // Arrange
+    let project = workspace();
+    let cook_directory = TempDir::new().unwrap();
+    let cargo = FakeCargo::new();
+
+    // Act
+    cargo_chef(&cook_directory)
+        .env("CARGO", cargo.path())
+        .env("CARGO_CHEF_RECIPE_PATH", "from-env.json")
+        .arg("cook")
+        .arg("--prepare-from")
+        .arg(project.path())
+        .assert()
+        .success();
+    let assert = cargo_chef(&cook_directory)
+        .env("CARGO", cargo.path())
+        .arg("cook")
+        .arg("--prepare-from")
+        .arg(project.path())
+        .args(["--recipe-path", "recipe.json"])
+        .assert();
+
+    // Assert
+    assert.failure().stderr(predicate::str::contains(
+        "`--prepare-from` cannot be used together with `--recipe-path`.",
+    ));
+}
+
+#[test]
+fn prepare_can_pretty_print_the_recipe() {
+    // Arrange
+    let project = workspace();
+    let compact_recipe: chef::Recipe =
+        serde_json::from_str(&std::fs::read_to_string(prepare(&project)).unwrap()).unwrap();
+
+    // Act
+    cargo_chef(&project)
+        .args(["prepare", "--pretty", "--recipe-path", "pretty.json"])
+        .assert()
+        .success();
+
+    // Assert
+    let pretty = std::fs::read_to_string(project.child("pretty.json").path()).unwrap();
+    assert!(pretty.lines().count() > 1);
+    let pretty_recipe: chef::Recipe = serde_json::from_str(&pretty).unwrap();
+    assert_eq!(pretty_recipe, compact_recipe);
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_copies_the_timings_report() {
+    // Arrange
+    let project = workspace();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+    let cargo = FakeCargo::with_body(
+        "mkdir -p target/debug target/cargo-timings\n\
+        echo report > target/cargo-timings/cargo-timing.html",
+    );
+
+    // Act
+    cargo_chef(&cook_directory)
+        .env("CARGO", cargo.path())
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .args(["--timings-dir", "reports"])
+        .assert()
+        .success();
+
+    // Assert
+    assert_eq!(cargo.invocations(), vec!["build --timings"]);
+    cook_directory
+        .child("reports")
+        .child("cargo-timing.html")
+        .assert("report\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_verifies_the_cleanup() {
+    // Arrange
+    let project = workspace();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+    let cargo = FakeCargo::with_body(
+        "mkdir -p target/debug/deps\n\
+        touch target/debug/deps/libproject_b-0123456789abcdef.rlib\n\
+        touch target/debug/deps/project_b.dll",
+    );
+    let cook = |verify_cleanup: bool| {
+        let mut command = cargo_chef(&cook_directory);
+        command
+            .env("CARGO", cargo.path())
+            .arg("cook")
+            .arg("--recipe-path")
+            .arg(&recipe_path);
+        if verify_cleanup {
+            command.arg("--verify-cleanup");
+        }
+        command.assert()
+    };
+
+    // Act
+    let unverified = cook(false);
+    let verified = cook(true);
+
+    // Assert
+    unverified.success();
+    verified.failure().stderr(
+        predicate::str::contains("Some compiled dummies were not cleaned up")
+            .and(predicate::str::contains("target/debug/deps/project_b.dll"))
+            .and(predicate::str::contains("libproject_b").not()),
+    );
+}
+
+#[cfg(all(unix, feature = "gzip"))]
+#[test]
+fn gzipped_recipes_round_trip() {
+    // Arrange
+    let project = workspace();
+    let cook_directory = TempDir::new().unwrap();
+    let cargo = FakeCargo::new();
+    let recipe_path = project.child("recipe.json.gz");
+
+    // Act
+    cargo_chef(&project)
+        .args(["prepare", "--recipe-path", "recipe.json.gz"])
+        .assert()
+        .success();
+    let cook = cargo_chef(&cook_directory)
+        .env("CARGO", cargo.path())
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(recipe_path.path())
+        .assert();
+
+    // Assert
+    let compressed = std::fs::read(recipe_path.path()).unwrap();
+    assert_eq!(compressed[..2], [0x1f, 0x8b]);
+    cook.success();
+    assert_eq!(cargo.invocations(), vec!["build"]);
+    cook_directory
+        .child("project_a")
+        .child("src")
+        .child("main.rs")
+        .assert("fn main() {}");
+}
+
+#[cfg(not(feature = "gzip"))]
+#[test]
+fn gzipped_recipes_require_the_gzip_feature() {
+    // Arrange
+    let project = workspace();
+
+    // Act
+    let assert = cargo_chef(&project)
+        .args(["prepare", "--recipe-path", "recipe.json.gz"])
+        .assert();
+
+    // Assert
+    assert
+        .failure()
+        .stderr(predicate::str::contains("built with the `gzip` feature"));
+    project
+        .child("recipe.json.gz")
+        .assert(predicate::path::missing());
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_restores_tool_config_files_before_running_clippy() {
+    // Arrange
+    let project = workspace();
+    project
+        .child("clippy.toml")
+        .write_str("msrv = \"1.70\"\n")
+        .unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    let cargo = FakeCargo::with_body("cp clippy.toml clippy-seen-by-cargo.toml");
+
+    // Act
+    cargo_chef(&project)
+        .args(["prepare", "--recipe-path", "recipe.json"])
+        .assert()
+        .success();
+    let cook = cargo_chef(&cook_directory)
+        .env("CARGO", cargo.path())
+        .arg("cook")
+        .arg("--clippy")
+        .arg("--recipe-path")
+        .arg(project.child("recipe.json").path())
+        .assert();
+
+    // Assert
+    cook.success();
+    assert_eq!(cargo.invocations()[0].split(' ').next(), Some("clippy"));
+    cook_directory
+        .child("clippy-seen-by-cargo.toml")
+        .assert("msrv = \"1.70\"\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_rejects_unknown_features_upfront() {
+    // Arrange
+    let project = workspace();
+    project
+        .child("project_b")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "project_b"
+version = "0.1.0"
+edition = "2018"
+
+[features]
+core = []
+"#,
+        )
+        .unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    let recipe_path = prepare(&project);
+    let cargo = FakeCargo::new();
+    let cook = |features: &str| {
+        cargo_chef(&cook_directory)
+            .env("CARGO", cargo.path())
+            .arg("cook")
+            .arg("--recipe-path")
+            .arg(&recipe_path)
+            .args(["--no-default-features", "--features", features])
+            .assert()
+    };
+
+    // Act
+    let misspelled = cook("cor");
+    let misspelled_in_package = cook("project_b/cor");
+
+    // Assert
+    misspelled
+        .failure()
+        .stderr(predicate::str::contains("There is no feature named `cor`"));
+    misspelled_in_package
+        .failure()
+        .stderr(predicate::str::contains(
+            "`project_b` does not declare a feature named `cor`",
+        ));
+    assert!(cargo.invocations().is_empty());
+    cook("core").success();
+    cook("project_b/core").success();
+    assert_eq!(cargo.invocations().len(), 2);
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_retries_failed_builds() {
+    // Arrange
+    let project = workspace();
+    let recipe_path = prepare(&project);
+    let cargo = FakeCargo::with_body(
+        "if [ ! -f failed-once ]; then touch failed-once; exit 101; fi
+        mkdir -p target/debug",
+    );
+    let cook = |cook_directory: &TempDir, retries: &str| {
+        cargo_chef(cook_directory)
+            .env("CARGO", cargo.path())
+            .arg("cook")
+            .arg("--recipe-path")
+            .arg(&recipe_path)
+            .args(["--retry", retries])
+            .assert()
+    };
+
+    // Act
+    let without_retries = cook(&TempDir::new().unwrap(), "0");
+    let with_retries = cook(&TempDir::new().unwrap(), "2");
+
+    // Assert
+    without_retries.failure();
+    with_retries
+        .success()
+        .stderr(predicate::str::contains("retrying in 1s (attempt 1 of 2)"));
+    assert_eq!(cargo.invocations(), vec!["build", "build", "build"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn relative_target_dir_is_resolved_against_the_current_directory() {
+    // Arrange
+    let project = workspace();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+    // Just like `cargo`, the fake one creates the target directory relative to the current one.
+    let cargo = FakeCargo::with_body(
+        "mkdir -p output/debug/deps
+        touch output/debug/deps/libproject_b-0123456789abcdef.rlib
+        touch output/debug/deps/libuuid-0123456789abcdef.rlib",
+    );
+
+    // Act
+    let cook = cargo_chef(&cook_directory)
+        .env("CARGO", cargo.path())
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .args(["--manifest-path", "project_a/Cargo.toml"])
+        .args(["--target-dir", "output"])
+        .assert();
+
+    // Assert
+    cook.success();
+    let deps = cook_directory.child("output/debug/deps");
+    deps.child("libproject_b-0123456789abcdef.rlib")
+        .assert(predicate::path::missing());
+    deps.child("libuuid-0123456789abcdef.rlib")
+        .assert(predicate::path::exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn target_dir_flag_takes_precedence_over_the_configured_one() {
+    // Arrange
+    let project = workspace();
+    project
+        .child(".cargo/config.toml")
+        .write_str("[build]\ntarget-dir = \"from-config\"\n")
+        .unwrap();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+    let cargo = FakeCargo::with_body(
+        "mkdir -p output/debug/deps from-config/debug/deps
+        touch output/debug/deps/libproject_b-0123456789abcdef.rlib
+        touch from-config/debug/deps/libproject_b-0123456789abcdef.rlib",
+    );
+
+    // Act
+    let cook = cargo_chef(&cook_directory)
+        .env("CARGO", cargo.path())
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .args(["--target-dir", "output"])
+        .assert();
+
+    // Assert
+    cook.success().stderr(predicate::str::contains(
+        "takes precedence over `build.target-dir`",
+    ));
+    cook_directory
+        .child("output/debug/deps/libproject_b-0123456789abcdef.rlib")
+        .assert(predicate::path::missing());
+    cook_directory
+        .child("from-config/debug/deps/libproject_b-0123456789abcdef.rlib")
+        .assert(predicate::path::exists());
+
+    // `CARGO_BUILD_TARGET_DIR` takes precedence over the configuration file as well
+    let cargo = FakeCargo::with_body(
+        "mkdir -p from-env/debug/deps from-config/debug/deps
+        touch from-env/debug/deps/libproject_b-0123456789abcdef.rlib
+        touch from-config/debug/deps/libproject_b-0123456789abcdef.rlib",
+    );
+    cargo_chef(&cook_directory)
+        .env("CARGO", cargo.path())
+        .env("CARGO_BUILD_TARGET_DIR", "from-env")
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .assert()
+        .success();
+    cook_directory
+        .child("from-env/debug/deps/libproject_b-0123456789abcdef.rlib")
+        .assert(predicate::path::missing());
+    cook_directory
+        .child("from-config/debug/deps/libproject_b-0123456789abcdef.rlib")
+        .assert(predicate::path::exists());
+
+    // ...but not over `--target-dir`
+    let cook = cargo_chef(&cook_directory)
+        .env("CARGO", cargo.path())
+        .env("CARGO_BUILD_TARGET_DIR", "from-env")
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .args(["--target-dir", "output"])
+        .assert();
+    cook.success()
+        .stderr(predicate::str::contains("from `CARGO_BUILD_TARGET_DIR`"));
+    cook_directory
+        .child("from-env/debug/deps/libproject_b-0123456789abcdef.rlib")
+        .assert(predicate::path::exists());
+}
+
+#[test]
+fn locked_cook_requires_a_lock_file_in_the_recipe() {
+    // Arrange
+    let project = workspace();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+
+    // Act
+    let assert = cargo_chef(&cook_directory)
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .arg("--locked")
+        .assert();
+
+    // Assert
+    assert.failure().stderr(predicate::str::contains(
+        "`--locked` requires a `Cargo.lock`, but the recipe doesn't have one",
+    ));
+    cook_directory
+        .child("Cargo.toml")
+        .assert(predicate::path::missing());
+}
+
+#[test]
+fn regenerate_lock_deletes_the_restored_lock_file() {
+    // Arrange
+    let project = workspace();
+    let status = std::process::Command::new(env!("CARGO"))
+        .args(["generate-lockfile", "--offline"])
+        .current_dir(project.path())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let recipe_path = prepare(&project);
+    let cook = |args: &[&str]| {
+        let cook_directory = TempDir::new().unwrap();
+        cargo_chef(&cook_directory)
+            .arg("cook")
+            .arg("--recipe-path")
+            .arg(&recipe_path)
+            .arg("--no-build")
+            .args(args)
+            .assert()
+            .success();
+        cook_directory
+    };
+
+    // Act
+    let restored = cook(&[]);
+    let regenerated = cook(&["--regenerate-lock"]);
+
+    // Assert
+    restored
+        .child("Cargo.lock")
+        .assert(predicate::path::exists());
+    regenerated
+        .child("Cargo.lock")
+        .assert(predicate::path::missing());
+    regenerated
+        .child("project_a/Cargo.toml")
+        .assert(predicate::path::exists());
+}
+
+#[test]
+fn masked_lock_file_is_frozen_compatible() {
+    // Arrange
+    let project = workspace();
+    project
+        .child("project_a")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "project_a"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+project_b = { path = "../project_b", version = "0.1.0" }
+"#,
+        )
+        .unwrap();
+    std::process::Command::new(env!("CARGO"))
+        .args(["generate-lockfile", "--offline"])
+        .current_dir(project.path())
+        .status()
+        .unwrap();
+    project
+        .child("Cargo.lock")
+        .assert(predicate::str::contains("project_b"));
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+
+    // Act
+    let cook = cargo_chef(&cook_directory)
+        .env("CARGO", env!("CARGO"))
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .args(["--frozen", "--locked", "--offline"])
+        .assert();
+
+    // Assert
+    cook.success();
+    cook_directory
+        .child("Cargo.lock")
+        .assert(predicate::str::contains("version = \"0.0.1\""));
+}
+
+#[test]
+fn prepare_a_nested_workspace_via_manifest_path() {
+    // Arrange
+    let project = workspace();
+    let nested = project.child("nested");
+    nested
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = ["inner"]
+"#,
+        )
+        .unwrap();
+    nested
+        .child("inner")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "inner"
+version = "0.1.0"
+edition = "2021"
+"#,
+        )
+        .unwrap();
+    nested
+        .child("inner")
+        .child("src")
+        .child("lib.rs")
+        .touch()
+        .unwrap();
+
+    // Act
+    let assert = cargo_chef(&project)
+        .args(["prepare", "--print-skeleton"])
+        .args(["--manifest-path", "nested/Cargo.toml"])
+        .assert();
+
+    // Assert
+    assert.success().stderr(
+        predicate::str::contains("  Cargo.toml\n")
+            .and(predicate::str::contains("  inner/Cargo.toml\n"))
+            .and(predicate::str::contains("project_a").not())
+            .and(predicate::str::contains("nested/").not()),
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_can_skip_the_cleanup() {
+    // Arrange
+    let project = workspace();
+    let recipe_path = prepare(&project);
+    let cargo = FakeCargo::with_body(
+        "mkdir -p target/debug/deps
+        touch target/debug/deps/libproject_b-0123456789abcdef.rlib",
+    );
+    let cook = |cook_directory: &TempDir, no_cleanup: bool| {
+        let mut command = cargo_chef(cook_directory);
+        command
+            .env("CARGO", cargo.path())
+            .arg("cook")
+            .arg("--recipe-path")
+            .arg(&recipe_path);
+        if no_cleanup {
+            command.arg("--no-cleanup");
+        }
+        command.assert().success();
+        cook_directory.child("target/debug/deps/libproject_b-0123456789abcdef.rlib")
+    };
+
+    let (first_cook, second_cook) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+
+    // Act
+    let cleaned_up = cook(&first_cook, false);
+    let kept = cook(&second_cook, true);
+
+    // Assert
+    cleaned_up.assert(predicate::path::missing());
+    kept.assert(predicate::path::exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_records_rustflags() {
+    // Arrange
+    let project = workspace();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+    let cargo = FakeCargo::new();
+    let cook = |rustflags: &str| {
+        cargo_chef(&cook_directory)
+            .env("CARGO", cargo.path())
+            .env("RUSTFLAGS", rustflags)
+            .arg("cook")
+            .arg("--recipe-path")
+            .arg(&recipe_path)
+            .args(["--json-summary", "summary.json"])
+            .assert()
+    };
+
+    // Act
+    let first_cook = cook("-C target-cpu=native");
+    let second_cook = cook("-C target-cpu=x86-64-v3");
+
+    // Assert
+    first_cook
+        .success()
+        .stderr(predicate::str::contains("different `RUSTFLAGS`").not());
+    second_cook
+        .success()
+        .stderr(predicate::str::contains("different `RUSTFLAGS`"));
+    cook_directory
+        .child("summary.json")
+        .assert(predicate::str::contains(
+            r#""rustflags": "-C target-cpu=x86-64-v3""#,
+        ));
+    cook_directory
+        .child("target/.cargo-chef-rustflags")
+        .assert(predicate::str::contains("x86-64-v3"));
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_logs_the_cargo_commands() {
+    // Arrange
+    let project = workspace();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+    let cargo = FakeCargo::new();
+
+    // Act
+    let cook = cargo_chef(&cook_directory)
+        .env("CARGO", cargo.path())
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .args(["--release", "--feature-set", "a", "--feature-set", "b"])
+        .args(["--command-log", "commands.log"])
+        .assert();
+
+    // Assert
+    cook.success();
+    cook_directory
+        .child("commands.log")
+        .assert("cargo build --release --features a\ncargo build --release --features b\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_can_check_then_build() {
+    // Arrange
+    let project = workspace();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+    let cargo = FakeCargo::new();
+
+    // Act
+    let cook = cargo_chef(&cook_directory)
+        .env("CARGO", cargo.path())
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .args(["--release", "--check", "--then-build"])
+        .assert();
+
+    // Assert
+    cook.success();
+    assert_eq!(
+        cargo.invocations(),
+        vec!["check --release", "build --release"]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_with_a_custom_cargo_home_only_cleans_up_the_target_directory() {
+    // Arrange
+    let project = workspace();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+    let cargo_home = TempDir::new().unwrap();
+    let cached_artifact = cargo_home.child("target/debug/deps/libproject_b-0123456789abcdef.rlib");
+    cached_artifact.touch().unwrap();
+    let cargo = FakeCargo::with_body(
+        "echo \"$CARGO_HOME\" > cargo-home.txt
+        mkdir -p target/debug/deps
+        touch target/debug/deps/libproject_b-0123456789abcdef.rlib",
+    );
+
+    // Act
+    let cook = cargo_chef(&cook_directory)
+        .env("CARGO", cargo.path())
+        .env("CARGO_HOME", cargo_home.path())
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .assert();
+
+    // Assert
+    cook.success();
+    cook_directory
+        .child("cargo-home.txt")
+        .assert(format!("{}\n", cargo_home.path().display()));
+    cook_directory
+        .child("target/debug/deps/libproject_b-0123456789abcdef.rlib")
+        .assert(predicate::path::missing());
+    cached_artifact.assert(predicate::path::exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_can_build_the_dependencies_only() {
+    // Arrange
+    let project = workspace();
+    project
+        .child("project_a")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "project_a"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+project_b = { path = "../project_b" }
+serde = "1"
+"#,
+        )
+        .unwrap();
+    project
+        .child("Cargo.lock")
+        .write_str(
+            r#"
+version = 3
+
+[[package]]
+name = "project_a"
+version = "0.1.0"
+dependencies = [
+ "project_b",
+ "serde",
+]
+
+[[package]]
+name = "project_b"
+version = "0.1.0"
+
+[[package]]
+name = "serde"
+version = "1.0.196"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#,
+        )
+        .unwrap();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+    let cargo = FakeCargo::new();
+
+    // Act
+    let cook = cargo_chef(&cook_directory)
+        .env("CARGO", cargo.path())
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .args(["--release", "--deps-only"])
+        .assert();
+
+    // Assert
+    cook.success();
+    // No workspace member is selected: `cargo` only compiles them when they're depended upon.
+    assert_eq!(
+        cargo.invocations(),
+        vec!["build --release --package serde@1.0.196"]
+    );
+}
+
+#[test]
+fn deps_only_conflicts_with_package_selection() {
+    // Arrange
+    let project = workspace();
+
+    // Act
+    let assert = cargo_chef(&project)
+        .args(["cook", "--deps-only", "--workspace"])
+        .assert();
+
+    // Assert
+    assert
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn workspace_without_members_is_a_no_op() {
+    // Arrange
+    let project = TempDir::new().unwrap();
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = []
+
+[workspace.package]
+edition = "2021"
+
+[workspace.dependencies]
+serde = "1"
+"#,
+        )
+        .unwrap();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+
+    // Act
+    let cook = cargo_chef(&cook_directory)
+        .env("CARGO", env!("CARGO"))
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .arg("--offline")
+        .assert();
+
+    // Assert
+    cook.success()
+        .stderr(predicate::str::contains("there is nothing to build"));
+    cook_directory
+        .child("Cargo.toml")
+        .assert(predicate::str::contains("[workspace.dependencies]"));
+    // Nothing was built, hence there are no flags to record
+    cook_directory
+        .child("target")
+        .assert(predicate::path::missing());
+}
+
+#[test]
+fn local_proc_macro_build_dependency() {
+    // Arrange
+    let project = TempDir::new().unwrap();
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = ["app", "codegen"]
+"#,
+        )
+        .unwrap();
+    project
+        .child("codegen")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "codegen"
+version = "0.1.0"
+edition = "2021"
+
+[lib]
+proc-macro = true
+"#,
+        )
+        .unwrap();
+    project
+        .child("codegen")
+        .child("src")
+        .child("lib.rs")
+        .write_str(
+            r#"
+use proc_macro::TokenStream;
+
+#[proc_macro]
+pub fn answer(_: TokenStream) -> TokenStream {
+    "42".parse().unwrap()
+}
+"#,
+        )
+        .unwrap();
+    project
+        .child("app")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2021"
+
+[build-dependencies]
+codegen = { path = "../codegen" }
+"#,
+        )
+        .unwrap();
+    project
+        .child("app")
+        .child("build.rs")
+        .write_str("fn main() {\n    assert_eq!(codegen::answer!(), 42);\n}\n")
+        .unwrap();
+    project
+        .child("app")
+        .child("src")
+        .child("main.rs")
+        .write_str("fn main() {}\n")
+        .unwrap();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+
+    // Act
+    let cook = cargo_chef(&cook_directory)
+        .env("CARGO", env!("CARGO"))
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .arg("--offline")
+        .assert();
+
+    // Assert
+    // The build script is a dummy as well: it doesn't invoke the (dummy) procedural macro.
+    cook.success();
+    cook_directory
+        .child("app")
+        .child("build.rs")
+        .assert("fn main() {}");
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_honours_the_rustc_wrapper_of_the_restored_config() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // Arrange
+    let wrapper_directory = TempDir::new().unwrap();
+    let marker = wrapper_directory.child("invocations.log");
+    let wrapper = wrapper_directory.child("wrapper");
+    wrapper
+        .write_str(&format!(
+            "#!/bin/sh\necho \"$@\" >> \"{}\"\nexec \"$@\"\n",
+            marker.path().display()
+        ))
+        .unwrap();
+    std::fs::set_permissions(wrapper.path(), std::fs::Permissions::from_mode(0o755)).unwrap();
+    let project = workspace();
+    project
+        .child(".cargo")
+        .child("config.toml")
+        .write_str(&format!(
+            "[build]\nrustc-wrapper = \"{}\"\n",
+            wrapper.path().display()
+        ))
+        .unwrap();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+
+    // Act
+    let cook = cargo_chef(&cook_directory)
+        .env("CARGO", env!("CARGO"))
+        .env_remove("RUSTC_WRAPPER")
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .arg("--offline")
+        .assert();
+
+    // Assert
+    cook.success();
+    cook_directory
+        .child(".cargo")
+        .child("config.toml")
+        .assert(predicate::str::contains("rustc-wrapper"));
+    marker.assert(
+        predicate::str::contains("--crate-name project_a")
+            .and(predicate::str::contains("--crate-name project_b")),
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_warns_about_features_spread_across_default_members() {
+    // Arrange
+    let project = workspace();
+    project
+        .child("project_b")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "project_b"
+version = "0.1.0"
+edition = "2018"
+
+[features]
+extra = []
+"#,
+        )
+        .unwrap();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+    let cargo = FakeCargo::new();
+    let cook = |args: &[&str]| {
+        cargo_chef(&cook_directory)
+            .env("CARGO", cargo.path())
+            .arg("cook")
+            .arg("--recipe-path")
+            .arg(&recipe_path)
+            .args(args)
+            .assert()
+            .success()
+    };
+    let warning = "`cargo` builds the 2 default members of the workspace";
+
+    // Act
+    let unqualified = cook(&["--features", "extra"]);
+    let selected = cook(&["--features", "extra", "--package", "project_b"]);
+    let qualified = cook(&["--features", "project_b/extra"]);
+
+    // Assert
+    unqualified.stderr(predicate::str::contains(warning));
+    selected.stderr(predicate::str::contains(warning).not());
+    qualified.stderr(predicate::str::contains(warning).not());
+}
+
+#[cfg(unix)]
+#[test]
+fn cook_requires_the_vendored_sources() {
+    // Arrange
+    let project = workspace();
+    project
+        .child(".cargo")
+        .child("config.toml")
+        .write_str(
+            r#"
+[source.crates-io]
+replace-with = "vendored-sources"
+
+[source.vendored-sources]
+directory = "vendor"
+"#,
+        )
+        .unwrap();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+    let cargo = FakeCargo::new();
+    let cook = || {
+        cargo_chef(&cook_directory)
+            .env("CARGO", cargo.path())
+            .arg("cook")
+            .arg("--recipe-path")
+            .arg(&recipe_path)
+            .arg("--offline")
+            .assert()
+    };
+
+    // Act
+    let without_vendor = cook();
+    cook_directory.child("vendor").create_dir_all().unwrap();
+    let with_vendor = cook();
+
+    // Assert
+    without_vendor.failure().stderr(predicate::str::contains(
+        "vendor`, which does not exist. Vendored dependencies are not part of the recipe",
+    ));
+    with_vendor.success();
+    assert_eq!(cargo.invocations(), vec!["build --offline"]);
+}
+
+#[test]
+fn cook_lists_the_external_dependencies() {
+    // Arrange
+    let project = workspace();
+    project
+        .child("Cargo.lock")
+        .write_str(
+            r#"
+version = 3
+
+[[package]]
+name = "project_a"
+version = "0.1.0"
+dependencies = [
+ "project_b",
+ "serde",
+ "tiny",
+ "uuid",
+]
+
+[[package]]
+name = "project_b"
+version = "0.1.0"
+
+[[package]]
+name = "serde"
+version = "1.0.196"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "tiny"
+version = "0.0.1"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "uuid"
+version = "1.7.0"
+source = "git+https://github.com/uuid-rs/uuid?branch=main#0123456789abcdef0123456789abcdef01234567"
+"#,
+        )
+        .unwrap();
+    let recipe_path = prepare(&project);
+    let cook_directory = TempDir::new().unwrap();
+
+    // Act
+    let assert = cargo_chef(&cook_directory)
+        .arg("cook")
+        .arg("--recipe-path")
+        .arg(&recipe_path)
+        .arg("--list-deps")
+        .assert();
+
+    // Assert
+    assert
+        .success()
+        .stdout("serde 1.0.196\ntiny 0.0.1\nuuid 1.7.0\n");
+    cook_directory
+        .child("Cargo.toml")
+        .assert(predicate::path::missing());
+}
+
+#[test]
+fn prepare_compares_the_dependencies_with_a_previous_recipe() {
+    // Arrange
+    let project = workspace();
+    let lock_file = |packages: &[(&str, &str)]| {
+        let mut lock_file = String::from("version = 3\n");
+        for (name, version) in packages {
+            lock_file.push_str(&format!(
+                "\n[[package]]\nname = \"{}\"\nversion = \"{}\"\nsource = \"registry+https://github.com/rust-lang/crates.io-index\"\n",
+                name, version
+            ));
+        }
+        lock_file
+    };
+    project
+        .child("Cargo.lock")
+        .write_str(&lock_file(&[("serde", "1.0.196"), ("tiny", "0.0.1")]))
+        .unwrap();
+    let recipe_path = prepare(&project);
+    let previous_recipe = std::fs::read_to_string(&recipe_path).unwrap();
+    project
+        .child("Cargo.lock")
+        .write_str(&lock_file(&[("newdep", "1.0.0"), ("serde", "1.0.197")]))
+        .unwrap();
+
+    // Act
+    let assert = cargo_chef(&project)
+        .arg("prepare")
+        .arg("--compare-to")
+        .arg(&recipe_path)
+        .assert();
+
+    // Assert
+    assert
+        .success()
+        .stdout("+ newdep 1.0.0\n~ serde 1.0.196 -> 1.0.197\n- tiny 0.0.1\n");
+    assert_eq!(
+        std::fs::read_to_string(&recipe_path).unwrap(),
+        previous_recipe
+    );
+}
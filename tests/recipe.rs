@@ -1,6 +1,6 @@
 use assert_fs::prelude::{FileTouch, FileWriteStr, PathChild, PathCreateDir};
 use assert_fs::TempDir;
-use chef::Recipe;
+use chef::{PrepareFilter, Recipe};
 
 fn quick_recipe(content: &str) -> Recipe {
     let recipe_directory = TempDir::new().unwrap();
@@ -58,3 +58,72 @@ path = "some-path.rs"
         );
     }
 }
+
+#[test]
+fn test_recipe_records_generator_version() {
+    let recipe = quick_recipe(
+        r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2018"
+"#,
+    );
+    assert_eq!(recipe.generator_version, env!("CARGO_PKG_VERSION"));
+    let recipe_json = serde_json::to_string(&recipe).unwrap();
+    let deserialized: Recipe = serde_json::from_str(&recipe_json).unwrap();
+    assert_eq!(deserialized.generator_version, env!("CARGO_PKG_VERSION"));
+}
+
+#[test]
+fn test_recipe_records_the_bin_filter() {
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = ["backend", "ci"]
+"#,
+        )
+        .unwrap();
+    for member in ["backend", "ci"] {
+        recipe_directory
+            .child(member)
+            .child("Cargo.toml")
+            .write_str(&format!(
+                r#"
+[package]
+name = "{}"
+version = "0.1.0"
+edition = "2018"
+"#,
+                member
+            ))
+            .unwrap();
+        recipe_directory
+            .child(member)
+            .child("src")
+            .child("main.rs")
+            .touch()
+            .unwrap();
+    }
+    let prepare = |member: Option<&str>| {
+        Recipe::prepare(
+            recipe_directory.path().canonicalize().unwrap(),
+            member.map(|member| member.to_string()),
+        )
+        .unwrap()
+    };
+
+    let recipe = prepare(Some("backend"));
+    assert_eq!(
+        recipe.prepared_for,
+        Some(PrepareFilter::Bin("backend".to_string()))
+    );
+    let recipe_json = serde_json::to_string(&recipe).unwrap();
+    let deserialized: Recipe = serde_json::from_str(&recipe_json).unwrap();
+    assert_eq!(deserialized.prepared_for, recipe.prepared_for);
+
+    assert_eq!(prepare(None).prepared_for, Some(PrepareFilter::None));
+}
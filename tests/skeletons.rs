@@ -3,7 +3,11 @@ use std::path::{Path, PathBuf};
 
 use assert_fs::prelude::*;
 use assert_fs::TempDir;
-use chef::Skeleton;
+use chef::{
+    missing_from_registry_cache, missing_targets, MinimumProjectArgs, OptimisationProfile,
+    PrepareArgs, RegistryPackage, Skeleton, TargetArgs, TargetKind, ToolchainRequirements,
+    DEFAULT_BUILD_INPUT_PATTERNS, DEFAULT_TOOL_CONFIG_FILES,
+};
 use expect_test::{expect, Expect};
 use predicates::prelude::*;
 
@@ -234,6 +238,7 @@ name = "foo"
 "#,
         )
         .touch("tests/foo.rs")
+        .file("rust-toolchain", "nightly")
         .build();
 
     // Act
@@ -279,6 +284,259 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
     );
 }
 
+#[test]
+pub fn no_std_dummies_without_panic_handler() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .bin_package(
+            ".",
+            r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2018"
+
+[[test]]
+name = "foo"
+"#,
+        )
+        .touch("tests/foo.rs")
+        .file("rust-toolchain", "nightly")
+        .build();
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+
+    // Act
+    skeleton
+        .build_minimum_project_with_args(
+            cook_directory.path(),
+            &MinimumProjectArgs {
+                no_std: true,
+                no_panic_handler: true,
+            },
+        )
+        .unwrap();
+
+    // Assert
+    cook_directory
+        .child("src/main.rs")
+        .assert("#![no_std]\n#![no_main]\n");
+    cook_directory.child("tests/foo.rs").assert(
+        r#"#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(test_runner)]
+
+#[no_mangle]
+pub extern "C" fn _init() {}
+
+fn test_runner(_: &[&dyn Fn()]) {}
+"#,
+    );
+}
+
+#[test]
+pub fn tests_no_std_stable_toolchain() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .lib_package(
+            ".",
+            r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2018"
+
+[[test]]
+name = "foo"
+"#,
+        )
+        .touch("tests/foo.rs")
+        .file(
+            "rust-toolchain.toml",
+            r#"
+[toolchain]
+channel = "stable"
+"#,
+        )
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), true)
+        .unwrap();
+
+    // Assert
+    cook_directory
+        .child("tests")
+        .child("foo.rs")
+        .assert("#![no_std]");
+    let output = std::process::Command::new(env!("CARGO"))
+        .args(["test", "--no-run", "--offline"])
+        .current_dir(cook_directory.path())
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+pub fn dummies_compile_with_the_default_edition() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .bin_package(
+            ".",
+            r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+build = "build.rs"
+
+[[test]]
+name = "foo"
+"#,
+        )
+        .touch("src/lib.rs")
+        .touch("build.rs")
+        .touch("tests/foo.rs")
+        .touch("benches/bar.rs")
+        .touch("examples/baz.rs")
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+
+    // Assert
+    let output = std::process::Command::new(env!("CARGO"))
+        .args(["check", "--all-targets", "--offline"])
+        .current_dir(cook_directory.path())
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+pub fn no_std_dummies_compile_with_the_default_edition() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .lib_package(
+            ".",
+            r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+
+[[test]]
+name = "foo"
+"#,
+        )
+        .touch("tests/foo.rs")
+        .file(
+            "rust-toolchain.toml",
+            r#"
+[toolchain]
+channel = "stable"
+"#,
+        )
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), true)
+        .unwrap();
+
+    // Assert
+    cook_directory
+        .child("tests")
+        .child("foo.rs")
+        .assert("#![no_std]");
+    let output = std::process::Command::new(env!("CARGO"))
+        .args(["test", "--no-run", "--offline"])
+        .current_dir(cook_directory.path())
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+pub fn no_std_harness_follows_the_edition_of_the_target() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[workspace]
+members = ["inherited", "overridden"]
+
+[workspace.package]
+edition = "2024"
+"#,
+        )
+        .lib_package(
+            "inherited",
+            r#"
+[package]
+name = "inherited"
+version = "0.1.0"
+edition.workspace = true
+
+[[test]]
+name = "foo"
+"#,
+        )
+        .touch("inherited/tests/foo.rs")
+        .lib_package(
+            "overridden",
+            r#"
+[package]
+name = "overridden"
+version = "0.1.0"
+edition = "2024"
+
+[[test]]
+name = "foo"
+edition = "2021"
+"#,
+        )
+        .touch("overridden/tests/foo.rs")
+        .file("rust-toolchain", "nightly")
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), true)
+        .unwrap();
+
+    // Assert
+    cook_directory
+        .child("inherited/tests/foo.rs")
+        .assert(predicate::str::contains("#[unsafe(no_mangle)]"));
+    cook_directory
+        .child("overridden/tests/foo.rs")
+        .assert(predicate::str::contains("\n#[no_mangle]"));
+}
+
 #[test]
 pub fn tests_no_harness() {
     // Arrange
@@ -479,6 +737,43 @@ edition = "2018"
     assert!(!manifest.contents.contains(r#"version = "1.2.3""#));
 }
 
+#[test]
+pub fn version_like_fields_are_not_masked() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[workspace]
+members = ["member"]
+"#,
+        )
+        .lib_package(
+            "member",
+            r#"
+[package]
+name = "member"
+version = "1.2.3"
+rust-version = "1.75"
+edition = "2021"
+"#,
+        )
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+
+    // Assert
+    let manifest = skeleton
+        .manifests
+        .iter()
+        .find(|manifest| manifest.relative_path == Path::new("member/Cargo.toml"))
+        .unwrap();
+    assert!(manifest.contents.contains(r#"version = "0.0.1""#));
+    assert!(manifest.contents.contains(r#"rust-version = "1.75""#));
+    assert!(manifest.contents.contains(r#"edition = "2021""#));
+}
+
 #[test]
 pub fn version_lock() {
     // Arrange
@@ -1208,7 +1503,7 @@ members = ["backend"]
         skeleton
             .manifests
             .iter()
-            .find(|manifest| manifest.relative_path == std::path::PathBuf::from("Cargo.toml"))
+            .find(|manifest| manifest.relative_path == Path::new("Cargo.toml"))
             .unwrap()
             .contents
             == gold
@@ -1725,7 +2020,7 @@ uuid = { version = "=0.8.0", features = ["v4"] }
             .unwrap()
             .contents
             .split('=')
-            .last()
+            .next_back()
             .unwrap()
             .replace(['[', ']', '"'], "")
             .trim()
@@ -1785,11 +2080,3667 @@ uuid = { version = "=0.8.0", features = ["v4"] }
     // with multiple binaries is probably a good idea here!
 }
 
-struct BuiltWorkspace {
-    directory: TempDir,
-}
-impl BuiltWorkspace {
-    fn path(&self) -> PathBuf {
-        self.directory.canonicalize().unwrap()
-    }
+#[test]
+pub fn members_selected_via_bin_keep_their_local_build_and_dev_dependencies() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[workspace]
+members = ["app", "codegen", "testutil", "unrelated"]
+"#,
+        )
+        .bin_package(
+            "app",
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2021"
+
+[build-dependencies]
+codegen = { path = "../codegen" }
+
+[dev-dependencies]
+testutil = { path = "../testutil" }
+"#,
+        )
+        .touch("app/build.rs")
+        .lib_package(
+            "codegen",
+            r#"
+[package]
+name = "codegen"
+version = "0.1.0"
+edition = "2021"
+"#,
+        )
+        .lib_package(
+            "testutil",
+            r#"
+[package]
+name = "testutil"
+version = "0.1.0"
+edition = "2021"
+"#,
+        )
+        .lib_package(
+            "unrelated",
+            r#"
+[package]
+name = "unrelated"
+version = "0.1.0"
+edition = "2021"
+"#,
+        )
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), Some("app".into())).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+
+    // Assert
+    let metadata = cargo_metadata::MetadataCommand::new()
+        .current_dir(cook_directory.path())
+        .no_deps()
+        .other_options(vec!["--offline".to_string()])
+        .exec()
+        .unwrap();
+    let mut members: Vec<_> = metadata
+        .workspace_packages()
+        .into_iter()
+        .map(|package| package.name.to_string())
+        .collect();
+    members.sort();
+    assert_eq!(members, vec!["app", "codegen", "testutil"]);
+    let output = std::process::Command::new(env!("CARGO"))
+        .args(["check", "--offline", "--tests", "--package", "app"])
+        .current_dir(cook_directory.path())
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+struct BuiltWorkspace {
+    directory: TempDir,
+}
+impl BuiltWorkspace {
+    fn path(&self) -> PathBuf {
+        self.directory.canonicalize().unwrap()
+    }
+}
+
+#[test]
+pub fn dummy_entrypoints_are_stable_for_every_target_kind() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[workspace]
+members = ["project_a", "project_b"]
+"#,
+        )
+        .lib_package(
+            "project_a",
+            r#"
+[package]
+name = "project_a"
+version = "0.1.0"
+edition = "2018"
+build = "build.rs"
+
+[[bin]]
+name = "a-bin"
+path = "src/main.rs"
+
+[[test]]
+name = "a-test"
+path = "tests/a-test.rs"
+
+[[bench]]
+name = "a-bench"
+path = "benches/a-bench.rs"
+
+[[example]]
+name = "a-example"
+path = "examples/a-example.rs"
+"#,
+        )
+        .touch_multiple(&[
+            "project_a/build.rs",
+            "project_a/src/main.rs",
+            "project_a/tests/a-test.rs",
+            "project_a/benches/a-bench.rs",
+            "project_a/examples/a-example.rs",
+        ])
+        .lib_package(
+            "project_b",
+            r#"
+[package]
+name = "project_b"
+version = "0.1.0"
+edition = "2018"
+
+[lib]
+proc-macro = true
+"#,
+        )
+        .file("rust-toolchain", "nightly")
+        .build();
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+
+    for no_std in [false, true] {
+        // Act
+        let first_cook = TempDir::new().unwrap();
+        skeleton
+            .build_minimum_project(first_cook.path(), no_std)
+            .unwrap();
+        let second_cook = TempDir::new().unwrap();
+        skeleton
+            .build_minimum_project(second_cook.path(), no_std)
+            .unwrap();
+
+        // Assert
+        let no_std_main = r#"#![no_std]
+#![no_main]
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
+"#;
+        let no_std_harness = r#"#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(test_runner)]
+
+#[no_mangle]
+pub extern "C" fn _init() {}
+
+fn test_runner(_: &[&dyn Fn()]) {}
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
+"#;
+        let expected = [
+            ("project_a/build.rs", "fn main() {}"),
+            (
+                "project_a/src/lib.rs",
+                if no_std { "#![no_std]" } else { "" },
+            ),
+            (
+                "project_a/src/main.rs",
+                if no_std { no_std_main } else { "fn main() {}" },
+            ),
+            (
+                "project_a/tests/a-test.rs",
+                if no_std { no_std_harness } else { "" },
+            ),
+            (
+                "project_a/benches/a-bench.rs",
+                if no_std { no_std_harness } else { "" },
+            ),
+            (
+                "project_a/examples/a-example.rs",
+                if no_std { no_std_main } else { "fn main() {}" },
+            ),
+            // `no_std` does not affect proc-macro crates
+            ("project_b/src/lib.rs", ""),
+        ];
+        for (path, content) in expected {
+            first_cook.child(path).assert(content);
+            second_cook.child(path).assert(content);
+        }
+    }
+}
+
+#[test]
+pub fn cleanup_host_build_scripts_when_cross_compiling() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .lib_package(
+            ".",
+            r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2018"
+build = "build.rs"
+"#,
+        )
+        .touch("build.rs")
+        .build();
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+    // Build scripts are compiled for the host, libraries for the target.
+    let host_build_script =
+        cook_directory.child("target/debug/build/test-dummy-0123456789abcdef/build-script-build");
+    let target_library = cook_directory
+        .child("target/aarch64-unknown-linux-gnu/debug/deps/libtest_dummy-0123456789abcdef.rlib");
+    let dependency = cook_directory
+        .child("target/aarch64-unknown-linux-gnu/debug/deps/libuuid-0123456789abcdef.rlib");
+    for artifact in [&host_build_script, &target_library, &dependency] {
+        artifact.touch().unwrap();
+    }
+
+    // Act
+    skeleton
+        .remove_compiled_dummies(
+            cook_directory.path(),
+            OptimisationProfile::Debug,
+            Some(vec!["aarch64-unknown-linux-gnu".to_string()]),
+            None,
+        )
+        .unwrap();
+
+    // Assert
+    host_build_script.assert(predicate::path::missing());
+    target_library.assert(predicate::path::missing());
+    dependency.assert(predicate::path::exists());
+}
+
+#[test]
+pub fn planned_files_match_the_files_written_by_build_minimum_project() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[workspace]
+members = ["project_a", "project_b"]
+"#,
+        )
+        .bin_package(
+            "project_a",
+            r#"
+[package]
+name = "project_a"
+version = "0.1.0"
+edition = "2018"
+build = "build.rs"
+"#,
+        )
+        .touch_multiple(&["project_a/build.rs", "project_a/tests/foo.rs"])
+        .lib_package(
+            "project_b",
+            r#"
+[package]
+name = "project_b"
+version = "0.1.0"
+edition = "2018"
+"#,
+        )
+        .touch("Cargo.lock")
+        .file(".cargo/config.toml", "")
+        .file("rust-toolchain", "stable")
+        .build();
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    let cook_path = cook_directory.path().canonicalize().unwrap();
+
+    // Act
+    let planned_files = skeleton.planned_files(&cook_path);
+    skeleton.build_minimum_project(&cook_path, false).unwrap();
+
+    // Assert
+    let mut written_files: Vec<PathBuf> = globwalk::GlobWalkerBuilder::new(&cook_path, "**")
+        .build()
+        .unwrap()
+        .map(|entry| entry.unwrap())
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.into_path())
+        .collect();
+    written_files.sort();
+    assert_eq!(planned_files, written_files);
+    assert_eq!(planned_files.len(), 10);
+}
+
+#[test]
+pub fn bins_sharing_the_same_path_are_sorted_by_name() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .bin_package(
+            ".",
+            r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2018"
+
+[[bin]]
+name = "second"
+path = "src/main.rs"
+required-features = ["b"]
+
+[[bin]]
+name = "first"
+path = "src/main.rs"
+required-features = ["a"]
+
+[features]
+a = []
+b = []
+"#,
+        )
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+
+    // Assert
+    check(
+        &skeleton.manifests[0].contents,
+        expect![[r#"
+            bench = []
+            test = []
+            example = []
+
+            [[bin]]
+            path = "src/main.rs"
+            name = "first"
+            plugin = false
+            proc-macro = false
+            required-features = ["a"]
+
+            [[bin]]
+            path = "src/main.rs"
+            name = "second"
+            plugin = false
+            proc-macro = false
+            required-features = ["b"]
+
+            [package]
+            name = "test-dummy"
+            edition = "2018"
+            version = "0.0.1"
+
+            [features]
+            a = []
+            b = []
+        "#]],
+    );
+    for _ in 0..5 {
+        assert_eq!(
+            skeleton,
+            Skeleton::derive(project.path(), None).unwrap(),
+            "skeletons of equal directories are not equal"
+        );
+    }
+}
+
+#[test]
+pub fn no_std_can_be_overridden_via_manifest_metadata() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[workspace]
+members = ["project_a", "project_b"]
+"#,
+        )
+        .bin_package(
+            "project_a",
+            r#"
+[package]
+name = "project_a"
+version = "0.1.0"
+edition = "2018"
+
+[package.metadata.cargo-chef]
+no-std = true
+"#,
+        )
+        .lib_package(
+            "project_b",
+            r#"
+[package]
+name = "project_b"
+version = "0.1.0"
+edition = "2018"
+
+[package.metadata.cargo-chef]
+no-std = false
+"#,
+        )
+        .build();
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+
+    for no_std in [false, true] {
+        // Act
+        let cook_directory = TempDir::new().unwrap();
+        skeleton
+            .build_minimum_project(cook_directory.path(), no_std)
+            .unwrap();
+
+        // Assert
+        cook_directory.child("project_a/src/main.rs").assert(
+            r#"#![no_std]
+#![no_main]
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
+"#,
+        );
+        cook_directory.child("project_b/src/lib.rs").assert("");
+    }
+}
+
+#[test]
+pub fn rust_toolchain_requirements() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .lib_package(
+            ".",
+            r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2021"
+"#,
+        )
+        .file(
+            "rust-toolchain.toml",
+            r#"
+[toolchain]
+channel = "1.75.0"
+components = ["rustfmt", "clippy"]
+targets = ["wasm32-unknown-unknown", "x86_64-unknown-linux-musl"]
+"#,
+        )
+        .build();
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    let (file_kind, contents) = skeleton.rust_toolchain_file.unwrap();
+
+    // Act
+    let requirements = ToolchainRequirements::parse(&file_kind, &contents).unwrap();
+
+    // Assert
+    assert_eq!(
+        requirements,
+        ToolchainRequirements {
+            targets: vec![
+                "wasm32-unknown-unknown".to_string(),
+                "x86_64-unknown-linux-musl".to_string()
+            ],
+            components: vec!["rustfmt".to_string(), "clippy".to_string()],
+        }
+    );
+    let commands: Vec<Vec<String>> = requirements
+        .rustup_commands()
+        .iter()
+        .map(|command| {
+            std::iter::once(command.get_program())
+                .chain(command.get_args())
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect()
+        })
+        .collect();
+    assert_eq!(
+        commands,
+        vec![
+            vec![
+                "rustup",
+                "target",
+                "add",
+                "wasm32-unknown-unknown",
+                "x86_64-unknown-linux-musl"
+            ],
+            vec!["rustup", "component", "add", "rustfmt", "clippy"],
+        ]
+    );
+}
+
+#[test]
+pub fn bare_rust_toolchain_has_no_requirements() {
+    let requirements =
+        ToolchainRequirements::parse(&chef::RustToolchainFile::Bare, "1.75.0").unwrap();
+
+    assert!(requirements.rustup_commands().is_empty());
+}
+
+#[test]
+pub fn targets_missing_from_the_toolchain_are_detected() {
+    let installed = "x86_64-unknown-linux-gnu\nwasm32-unknown-unknown\n";
+    let requested = vec![
+        "wasm32-unknown-unknown".to_string(),
+        "aarch64-unknown-linux-musl".to_string(),
+        "targets/custom.json".to_string(),
+    ];
+
+    let missing = missing_targets(installed, &requested);
+
+    assert_eq!(missing, vec!["aarch64-unknown-linux-musl".to_string()]);
+}
+
+#[test]
+pub fn workspace_package_fields_are_preserved() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[workspace]
+members = ["project_a"]
+
+[workspace.package]
+version = "0.2.0"
+authors = ["Jane Doe <jane@example.com>", "John Doe <john@example.com>"]
+description = "A test workspace"
+homepage = "https://example.com"
+repository = "https://github.com/example/example"
+license = "MIT"
+edition = "2021"
+"#,
+        )
+        .lib_package(
+            "project_a",
+            r#"
+[package]
+name = "project_a"
+version.workspace = true
+authors.workspace = true
+description.workspace = true
+homepage.workspace = true
+repository.workspace = true
+license.workspace = true
+edition.workspace = true
+"#,
+        )
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+
+    // Assert
+    check(
+        &skeleton.manifests[0].contents,
+        expect![[r#"
+            [workspace]
+            members = ["project_a"]
+
+            [workspace.package]
+            edition = "2021"
+            version = "0.0.1"
+            authors = ["Jane Doe <jane@example.com>", "John Doe <john@example.com>"]
+            description = "A test workspace"
+            homepage = "https://example.com"
+            license = "MIT"
+            repository = "https://github.com/example/example"
+        "#]],
+    );
+}
+
+#[test]
+pub fn crlf_line_endings_are_normalized() {
+    // Arrange
+    let manifest = r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2021"
+description = """
+A multi-line
+description
+"""
+
+[dependencies]
+"#;
+    let config = r#"
+[build]
+rustflags = ["-C", "target-cpu=native"]
+"#;
+    let toolchain = r#"
+[toolchain]
+channel = "1.75.0"
+"#;
+    let build_project = |line_ending: &str| {
+        CargoWorkspace::new()
+            .lib_package(".", &manifest.replace('\n', line_ending))
+            .file(".cargo/config.toml", &config.replace('\n', line_ending))
+            .file("rust-toolchain.toml", &toolchain.replace('\n', line_ending))
+            .build()
+    };
+    let unix_project = build_project("\n");
+    let windows_project = build_project("\r\n");
+
+    // Act
+    let unix_skeleton = Skeleton::derive(unix_project.path(), None).unwrap();
+    let windows_skeleton = Skeleton::derive(windows_project.path(), None).unwrap();
+
+    // Assert
+    assert_eq!(unix_skeleton, windows_skeleton);
+    assert!(!serde_json::to_string(&windows_skeleton)
+        .unwrap()
+        .contains("\\r"));
+}
+
+#[test]
+pub fn crate_type_override() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[workspace]
+members = ["project_a", "project_b"]
+"#,
+        )
+        .bin_package(
+            "project_a",
+            r#"
+[package]
+name = "project_a"
+version = "0.1.0"
+edition = "2018"
+"#,
+        )
+        .lib_package(
+            "project_b",
+            r#"
+[package]
+name = "project_b"
+version = "0.1.0"
+edition = "2018"
+"#,
+        )
+        .build();
+    let mut skeleton = Skeleton::derive(project.path(), None).unwrap();
+
+    // Act
+    skeleton
+        .set_crate_type("project_b", &["cdylib".to_string(), "rlib".to_string()])
+        .unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+
+    // Assert
+    cook_directory
+        .child("project_b/Cargo.toml")
+        .assert(predicate::str::contains(
+            r#"crate-type = ["cdylib", "rlib"]"#,
+        ));
+    assert!(skeleton.set_crate_type("project_a", &[]).is_err());
+    assert!(skeleton.set_crate_type("project_c", &[]).is_err());
+
+    let dummies = [
+        "target/debug/libproject_b.so",
+        "target/debug/libproject_b.rlib",
+        "target/debug/deps/libproject_b-0123456789abcdef.so",
+        "target/debug/deps/libproject_b-0123456789abcdef.rlib",
+    ];
+    for dummy in dummies {
+        cook_directory.child(dummy).touch().unwrap();
+    }
+    skeleton
+        .remove_compiled_dummies(
+            cook_directory.path(),
+            OptimisationProfile::Debug,
+            None,
+            None,
+        )
+        .unwrap();
+    for dummy in dummies {
+        cook_directory
+            .child(dummy)
+            .assert(predicate::path::missing());
+    }
+}
+
+#[test]
+pub fn derive_from_a_member_directory() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[workspace]
+members = ["project_a", "project_b"]
+"#,
+        )
+        .bin_package(
+            "project_a",
+            r#"
+[package]
+name = "project_a"
+version = "0.1.0"
+edition = "2018"
+"#,
+        )
+        .lib_package(
+            "project_b",
+            r#"
+[package]
+name = "project_b"
+version = "0.1.0"
+edition = "2018"
+"#,
+        )
+        .touch("Cargo.lock")
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path().join("project_a"), None).unwrap();
+
+    // Assert
+    let relative_paths: Vec<_> = skeleton
+        .manifests
+        .iter()
+        .map(|manifest| manifest.relative_path.clone())
+        .collect();
+    assert_eq!(
+        relative_paths,
+        vec![
+            PathBuf::from("Cargo.toml"),
+            PathBuf::from("project_a/Cargo.toml"),
+            PathBuf::from("project_b/Cargo.toml"),
+        ]
+    );
+    assert!(skeleton.lock_file.is_some());
+}
+
+#[test]
+pub fn dump_cargo_metadata() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .lib_package(
+            ".",
+            r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2018"
+"#,
+        )
+        .build();
+    let dump_directory = TempDir::new().unwrap();
+    let dump_path = dump_directory.child("metadata.json");
+
+    // Act
+    let skeleton = Skeleton::derive_with_args(
+        project.path(),
+        &PrepareArgs {
+            dump_cargo_metadata: Some(dump_path.path().to_path_buf()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    // Assert
+    let dumped = std::fs::read_to_string(dump_path.path()).unwrap();
+    let metadata: cargo_metadata::Metadata = serde_json::from_str(&dumped).unwrap();
+    assert_eq!(metadata.workspace_packages()[0].name, "test-dummy");
+    assert_eq!(skeleton, Skeleton::derive(project.path(), None).unwrap());
+}
+
+#[test]
+pub fn only_selected_target_kinds_get_a_dummy_entrypoint() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .lib_package(
+            ".",
+            r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2018"
+
+[[bench]]
+name = "basics"
+harness = false
+
+[[test]]
+name = "foo"
+path = "tests/foo.rs"
+
+[[example]]
+name = "tested"
+path = "examples/tested.rs"
+"#,
+        )
+        .touch("benches/basics.rs")
+        .touch("tests/foo.rs")
+        .touch("tests/bar.rs")
+        .touch("examples/tested.rs")
+        .build();
+    let mut skeleton = Skeleton::derive(project.path(), None).unwrap();
+
+    // Act
+    skeleton.retain_targets(&TargetArgs {
+        benches: true,
+        tests: false,
+        examples: false,
+        all_targets: false,
+        example: None,
+    });
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+
+    // Assert
+    cook_directory
+        .child("benches")
+        .child("basics.rs")
+        .assert("fn main() {}");
+    cook_directory.child("src").child("lib.rs").assert("");
+    cook_directory
+        .child("tests")
+        .child("foo.rs")
+        .assert(predicate::path::missing());
+    cook_directory
+        .child("tests")
+        .child("bar.rs")
+        .assert(predicate::path::missing());
+    cook_directory
+        .child("examples")
+        .child("tested.rs")
+        .assert(predicate::path::missing());
+}
+
+#[test]
+pub fn targets_declared_without_a_path_keep_their_dummy_entrypoint() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .lib_package(
+            ".",
+            r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2018"
+
+[[test]]
+name = "integ"
+
+[[bench]]
+name = "perf"
+harness = false
+
+[[example]]
+name = "demo"
+"#,
+        )
+        .touch("tests/integ.rs")
+        .touch("benches/perf.rs")
+        .touch("examples/demo.rs")
+        .touch("examples/other.rs")
+        .build();
+    let mut skeleton = Skeleton::derive(project.path(), None).unwrap();
+
+    // Act
+    skeleton.retain_targets(&TargetArgs {
+        benches: false,
+        tests: false,
+        examples: false,
+        all_targets: false,
+        example: None,
+    });
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+
+    // Assert
+    cook_directory
+        .child("tests")
+        .child("integ.rs")
+        .assert(predicate::path::exists());
+    cook_directory
+        .child("benches")
+        .child("perf.rs")
+        .assert(predicate::path::exists());
+    cook_directory
+        .child("examples")
+        .child("demo.rs")
+        .assert(predicate::path::exists());
+    cook_directory
+        .child("examples")
+        .child("other.rs")
+        .assert(predicate::path::missing());
+    let output = std::process::Command::new(env!("CARGO"))
+        .args(["build", "--offline"])
+        .current_dir(cook_directory.path())
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+pub fn versionless_local_path_dependencies() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[workspace]
+members = ["a", "b"]
+    "#,
+        )
+        .lib_package(
+            "a",
+            r#"
+[package]
+name = "a"
+version = "0.5.0"
+
+[dependencies]
+b = { path = "../b" }
+    "#,
+        )
+        .lib_package(
+            "b",
+            r#"
+[package]
+name = "b"
+version = "0.2.1"
+    "#,
+        )
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+
+    // Assert
+    check(
+        &skeleton.manifests[1].contents,
+        expect![[r#"
+            bin = []
+            bench = []
+            test = []
+            example = []
+
+            [package]
+            name = "a"
+            version = "0.0.1"
+
+            [dependencies.b]
+            path = "../b"
+
+            [lib]
+            path = "src/lib.rs"
+            name = "a"
+            plugin = false
+            proc-macro = false
+            required-features = []
+            crate-type = ["rlib"]
+        "#]],
+    );
+    check(
+        &skeleton.manifests[2].contents,
+        expect![[r#"
+            bin = []
+            bench = []
+            test = []
+            example = []
+
+            [package]
+            name = "b"
+            version = "0.0.1"
+
+            [lib]
+            path = "src/lib.rs"
+            name = "b"
+            plugin = false
+            proc-macro = false
+            required-features = []
+            crate-type = ["rlib"]
+        "#]],
+    );
+}
+
+#[test]
+pub fn explicit_bin_alongside_src_main() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .bin_package(
+            ".",
+            r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2018"
+
+[[bin]]
+name = "cli"
+path = "src/cli.rs"
+"#,
+        )
+        .touch("src/cli.rs")
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+
+    // Assert
+    let bins: Vec<_> = skeleton.manifests[0]
+        .targets
+        .iter()
+        .filter(|target| target.kind() == &TargetKind::Bin)
+        .map(|target| (target.name().to_string(), target.path().to_path_buf()))
+        .collect();
+    assert_eq!(
+        bins,
+        vec![
+            ("cli".to_string(), PathBuf::from("src/cli.rs")),
+            ("test-dummy".to_string(), PathBuf::from("src/main.rs")),
+        ]
+    );
+    cook_directory
+        .child("src")
+        .child("cli.rs")
+        .assert("fn main() {}");
+    cook_directory
+        .child("src")
+        .child("main.rs")
+        .assert("fn main() {}");
+}
+
+#[test]
+pub fn cleanup_check_metadata() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .lib_package(
+            ".",
+            r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2018"
+"#,
+        )
+        .build();
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+    // `cargo check` emits `.rmeta` files, with hashed names, in `deps`.
+    let library_metadata =
+        cook_directory.child("target/debug/deps/libtest_dummy-0123456789abcdef.rmeta");
+    let dependency_metadata =
+        cook_directory.child("target/debug/deps/libuuid-0123456789abcdef.rmeta");
+    for artifact in [&library_metadata, &dependency_metadata] {
+        artifact.touch().unwrap();
+    }
+
+    // Act
+    skeleton
+        .remove_compiled_dummies(
+            cook_directory.path(),
+            OptimisationProfile::Debug,
+            None,
+            None,
+        )
+        .unwrap();
+
+    // Assert
+    library_metadata.assert(predicate::path::missing());
+    dependency_metadata.assert(predicate::path::exists());
+}
+
+#[test]
+pub fn minify_strips_non_essential_sections() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .lib_package(
+            ".",
+            r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2018"
+description = "A dummy crate"
+authors = ["Jane Doe"]
+keywords = ["dummy"]
+categories = ["development-tools"]
+readme = "README.md"
+
+[package.metadata.docs.rs]
+all-features = true
+
+[badges]
+maintenance = { status = "actively-developed" }
+
+[dependencies]
+uuid = { version = "=0.8.0", features = ["v4"] }
+"#,
+        )
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive_with_args(
+        project.path(),
+        &PrepareArgs {
+            minify: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    // Assert
+    check(
+        &skeleton.manifests[0].contents,
+        expect![[r#"
+            bin = []
+            bench = []
+            test = []
+            example = []
+
+            [package]
+            name = "test-dummy"
+            edition = "2018"
+            version = "0.0.1"
+
+            [package.metadata.docs.rs]
+            all-features = true
+
+            [dependencies.uuid]
+            version = "=0.8.0"
+            features = ["v4"]
+
+            [lib]
+            path = "src/lib.rs"
+            name = "test_dummy"
+            plugin = false
+            proc-macro = false
+            edition = "2018"
+            required-features = []
+            crate-type = ["rlib"]
+        "#]],
+    );
+}
+
+#[test]
+pub fn capture_build_inputs_of_codegen_build_scripts() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[workspace]
+members = ["grpc", "plain"]
+"#,
+        )
+        .lib_package(
+            "grpc",
+            r#"
+[package]
+name = "grpc"
+version = "0.1.0"
+edition = "2018"
+
+[build-dependencies]
+tonic-build = "0.10"
+"#,
+        )
+        .file("grpc/proto/helloworld.proto", "syntax = \"proto3\";")
+        .file("grpc/service.proto", "syntax = \"proto3\";\r\n")
+        .touch("grpc/build.rs")
+        .lib_package(
+            "plain",
+            r#"
+[package]
+name = "plain"
+version = "0.1.0"
+edition = "2018"
+"#,
+        )
+        .file("plain/proto/ignored.proto", "syntax = \"proto3\";")
+        .build();
+    // Binary descriptor sets are not valid UTF-8: they are skipped.
+    std::fs::write(
+        project.path().join("grpc/proto/descriptor.bin"),
+        [0xff, 0xfe, 0x00],
+    )
+    .unwrap();
+    let default_patterns: Vec<String> = DEFAULT_BUILD_INPUT_PATTERNS
+        .iter()
+        .map(|pattern| pattern.to_string())
+        .collect();
+
+    // Act
+    let skeleton = Skeleton::derive_with_args(
+        project.path(),
+        &PrepareArgs {
+            build_inputs: Some(default_patterns),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+
+    // Assert
+    let captured: Vec<_> = skeleton
+        .extra_files
+        .iter()
+        .map(|file| file.relative_path.clone())
+        .collect();
+    assert_eq!(
+        captured,
+        vec![
+            PathBuf::from("grpc/proto/helloworld.proto"),
+            PathBuf::from("grpc/service.proto"),
+        ]
+    );
+    cook_directory
+        .child("grpc/proto/helloworld.proto")
+        .assert("syntax = \"proto3\";");
+    // Line endings are normalised, like for all other files in the recipe.
+    cook_directory
+        .child("grpc/service.proto")
+        .assert("syntax = \"proto3\";\n");
+    cook_directory
+        .child("plain/proto/ignored.proto")
+        .assert(predicate::path::missing());
+
+    // Custom patterns replace the default ones
+    let skeleton = Skeleton::derive_with_args(
+        project.path(),
+        &PrepareArgs {
+            build_inputs: Some(vec!["/*.proto".to_string()]),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(skeleton.extra_files.len(), 1);
+    assert_eq!(
+        skeleton.extra_files[0].relative_path,
+        PathBuf::from("grpc/service.proto")
+    );
+
+    // Nothing is captured by default
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    assert!(skeleton.extra_files.is_empty());
+}
+
+#[test]
+pub fn features_are_sorted() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .lib_package(
+            ".",
+            r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2018"
+
+[features]
+zeta = ["gamma", "beta", "uuid/v4"]
+default = ["zeta", "alpha"]
+gamma = ["dep:uuid", "alpha"]
+alpha = []
+beta = ["alpha", "gamma"]
+
+[dependencies]
+uuid = { version = "=0.8.0", optional = true }
+"#,
+        )
+        .build();
+
+    // Act
+    let skeletons: Vec<_> = (0..5)
+        .map(|_| Skeleton::derive(project.path(), None).unwrap())
+        .collect();
+
+    // Assert
+    for skeleton in &skeletons[1..] {
+        assert_eq!(skeleton, &skeletons[0]);
+    }
+    check(
+        &skeletons[0].manifests[0].contents,
+        expect![[r#"
+            bin = []
+            bench = []
+            test = []
+            example = []
+
+            [package]
+            name = "test-dummy"
+            edition = "2018"
+            version = "0.0.1"
+
+            [dependencies.uuid]
+            version = "=0.8.0"
+            optional = true
+
+            [features]
+            alpha = []
+            beta = ["alpha", "gamma"]
+            default = ["alpha", "zeta"]
+            gamma = ["alpha", "dep:uuid"]
+            zeta = ["beta", "gamma", "uuid/v4"]
+
+            [lib]
+            path = "src/lib.rs"
+            name = "test_dummy"
+            plugin = false
+            proc-macro = false
+            edition = "2018"
+            required-features = []
+            crate-type = ["rlib"]
+        "#]],
+    );
+}
+
+#[test]
+pub fn cleanup_examples() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .lib_package(
+            ".",
+            r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2018"
+"#,
+        )
+        .touch("examples/my-example.rs")
+        .touch("examples/my-example-two.rs")
+        .build();
+    let mut skeleton = Skeleton::derive(project.path(), None).unwrap();
+    skeleton.retain_targets(&TargetArgs {
+        benches: false,
+        tests: false,
+        examples: false,
+        all_targets: false,
+        example: Some(vec!["my-example".to_string()]),
+    });
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+    let examples = cook_directory.child("target/debug/examples");
+    let artifacts = [
+        examples.child("my-example"),
+        examples.child("my-example.d"),
+        examples.child("my_example-0123456789abcdef"),
+        examples.child("my_example-0123456789abcdef.d"),
+    ];
+    for artifact in &artifacts {
+        artifact.touch().unwrap();
+    }
+    // Not part of the cooked skeleton
+    let other_example = examples.child("my_example_two-0123456789abcdef");
+    other_example.touch().unwrap();
+
+    // Act
+    skeleton
+        .remove_compiled_dummies(
+            cook_directory.path(),
+            OptimisationProfile::Debug,
+            None,
+            None,
+        )
+        .unwrap();
+
+    // Assert
+    cook_directory
+        .child("examples/my-example.rs")
+        .assert("fn main() {}");
+    cook_directory
+        .child("examples/my-example-two.rs")
+        .assert(predicate::path::missing());
+    for artifact in &artifacts {
+        artifact.assert(predicate::path::missing());
+    }
+    other_example.assert(predicate::path::exists());
+}
+
+#[cfg(unix)]
+#[test]
+pub fn non_utf8_paths_are_rejected_with_a_clear_error() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    // Arrange
+    let directory = PathBuf::from(OsStr::from_bytes(b"project-\xff"));
+    let project = CargoWorkspace::new()
+        .lib_package(
+            &directory,
+            r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2018"
+"#,
+        )
+        .build();
+
+    // Act
+    let error = Skeleton::derive(project.path().join(&directory), None).unwrap_err();
+
+    // Assert
+    let message = format!("{:#}", error);
+    assert!(
+        message.contains("does not support paths that are not valid UTF-8")
+            && message.contains("project-\\xFF"),
+        "{}",
+        message
+    );
+}
+
+#[test]
+pub fn workspace_glob_members_and_exclude_are_preserved() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[workspace]
+members = ["crates/*", "tools/*"]
+exclude = ["crates/experimental-*", "tools/legacy"]
+    "#,
+        )
+        .lib_package(
+            "crates/project_a",
+            r#"
+[package]
+name = "project_a"
+version = "0.0.1"
+    "#,
+        )
+        .lib_package(
+            "crates/experimental-b",
+            r#"
+[package]
+name = "experimental-b"
+version = "0.0.1"
+    "#,
+        )
+        .bin_package(
+            "tools/project_c",
+            r#"
+[package]
+name = "project_c"
+version = "0.0.1"
+    "#,
+        )
+        .bin_package(
+            "tools/legacy",
+            r#"
+[package]
+name = "legacy"
+version = "0.0.1"
+    "#,
+        )
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+
+    // Assert
+    // `cargo` does not expand globs in `exclude`: `crates/experimental-b` is still a member.
+    let relative_paths: Vec<_> = skeleton
+        .manifests
+        .iter()
+        .map(|manifest| manifest.relative_path.clone())
+        .collect();
+    assert_eq!(
+        relative_paths,
+        vec![
+            PathBuf::from("Cargo.toml"),
+            PathBuf::from("crates/experimental-b/Cargo.toml"),
+            PathBuf::from("crates/project_a/Cargo.toml"),
+            PathBuf::from("tools/project_c/Cargo.toml"),
+        ]
+    );
+    check(
+        &skeleton.manifests[0].contents,
+        expect![[r#"
+            [workspace]
+            members = ["crates/*", "tools/*"]
+            exclude = ["crates/experimental-*", "tools/legacy"]
+        "#]],
+    );
+}
+
+#[test]
+pub fn links() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .lib_package(
+            ".",
+            r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2018"
+links = "foo"
+"#,
+        )
+        .file(
+            "build.rs",
+            "fn main() { println!(\"cargo:rustc-link-lib=foo\"); }",
+        )
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+
+    // Assert
+    check(
+        &skeleton.manifests[0].contents,
+        expect![[r#"
+            bin = []
+            bench = []
+            test = []
+            example = []
+
+            [package]
+            name = "test-dummy"
+            edition = "2018"
+            version = "0.0.1"
+            build = "build.rs"
+            links = "foo"
+
+            [lib]
+            path = "src/lib.rs"
+            name = "test_dummy"
+            plugin = false
+            proc-macro = false
+            edition = "2018"
+            required-features = []
+            crate-type = ["rlib"]
+        "#]],
+    );
+    cook_directory.child("build.rs").assert("fn main() {}");
+}
+
+#[test]
+pub fn cleanup_honours_the_target_dir_in_config() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .lib_package(
+            ".",
+            r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2018"
+"#,
+        )
+        .file(
+            ".cargo/config.toml",
+            r#"
+[build]
+target-dir = "build-output"
+"#,
+        )
+        .build();
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+    let library =
+        cook_directory.child("build-output/debug/deps/libtest_dummy-0123456789abcdef.rlib");
+    library.touch().unwrap();
+
+    // Act
+    skeleton
+        .remove_compiled_dummies(
+            cook_directory.path(),
+            OptimisationProfile::Debug,
+            None,
+            None,
+        )
+        .unwrap();
+
+    // Assert
+    library.assert(predicate::path::missing());
+}
+
+#[test]
+pub fn bins_outside_src() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[workspace]
+members = ["project_a"]
+"#,
+        )
+        .bin_package(
+            "project_a",
+            r#"
+[package]
+name = "project_a"
+version = "0.1.0"
+edition = "2018"
+
+[[bin]]
+name = "tool"
+path = "tools/sub/tool.rs"
+
+[[bin]]
+name = "shared"
+path = "../shared/tool.rs"
+"#,
+        )
+        .touch("project_a/tools/sub/tool.rs")
+        .touch("shared/tool.rs")
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+
+    // Assert
+    cook_directory
+        .child("project_a/tools/sub/tool.rs")
+        .assert("fn main() {}");
+    // Still within the project root
+    cook_directory
+        .child("shared/tool.rs")
+        .assert("fn main() {}");
+}
+
+#[test]
+pub fn bins_outside_the_project_root_are_rejected() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .bin_package(
+            "project_a",
+            r#"
+[package]
+name = "project_a"
+version = "0.1.0"
+edition = "2018"
+
+[[bin]]
+name = "tool"
+path = "../shared/tool.rs"
+"#,
+        )
+        .touch("shared/tool.rs")
+        .file("project_a/.cargo/config.toml", "[build]\njobs = 1\n")
+        .build();
+    let skeleton = Skeleton::derive(project.path().join("project_a"), None).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+
+    // Act
+    let error = skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap_err();
+
+    // Assert
+    assert!(
+        error.to_string().contains(
+            "The entrypoint of `tool` (../shared/tool.rs) is outside of the project root"
+        ),
+        "{}",
+        error
+    );
+    cook_directory
+        .child("../shared/tool.rs")
+        .assert(predicate::path::missing());
+    // Nothing is written for a rejected recipe
+    assert_eq!(std::fs::read_dir(cook_directory.path()).unwrap().count(), 0);
+}
+
+#[test]
+pub fn mask_local_crate_versions_in_patches() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[workspace]
+members = ["app", "mylib"]
+
+[patch.crates-io]
+mylib = { path = "mylib", version = "1.2.3" }
+"#,
+        )
+        .bin_package(
+            "app",
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+mylib = "1.2"
+"#,
+        )
+        .lib_package(
+            "mylib",
+            r#"
+[package]
+name = "mylib"
+version = "1.2.3"
+edition = "2018"
+"#,
+        )
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+
+    // Assert
+    check(
+        &skeleton.manifests[0].contents,
+        expect![[r#"
+            [workspace]
+            members = ["app", "mylib"]
+
+            [patch.crates-io.mylib]
+            version = "0.0.1"
+            path = "mylib"
+        "#]],
+    );
+}
+
+#[test]
+pub fn find_compiled_dummies_missed_by_the_cleanup() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .lib_package(
+            ".",
+            r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2018"
+"#,
+        )
+        .build();
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+    let deps = cook_directory.child("target/debug/deps");
+    let library = deps.child("libtest_dummy-0123456789abcdef.rlib");
+    let dep_info = deps.child("test_dummy-0123456789abcdef.d");
+    // Windows libraries do not have a `lib` prefix, the cleanup misses them.
+    let windows_library = deps.child("test_dummy.dll");
+    // Binaries sharing the library name are not dummies.
+    let binary = cook_directory.child("target/debug/test_dummy.exe");
+    let debug_info = cook_directory.child("target/debug/test_dummy.pdb");
+    for artifact in [&library, &dep_info, &windows_library, &binary, &debug_info] {
+        artifact.touch().unwrap();
+    }
+
+    // Act
+    skeleton
+        .remove_compiled_dummies(
+            cook_directory.path(),
+            OptimisationProfile::Debug,
+            None,
+            None,
+        )
+        .unwrap();
+    let leftovers = skeleton
+        .find_compiled_dummies(
+            cook_directory.path(),
+            OptimisationProfile::Debug,
+            None,
+            None,
+        )
+        .unwrap();
+
+    // Assert
+    assert_eq!(leftovers, vec![windows_library.path().to_path_buf()]);
+}
+
+#[test]
+pub fn uppercase_package_names_are_masked_and_cleaned_up() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .lib_package(
+            ".",
+            r#"
+[package]
+name = "My-Crate"
+version = "1.2.3"
+edition = "2018"
+build = "build.rs"
+"#,
+        )
+        .touch("build.rs")
+        .file(
+            "Cargo.lock",
+            r#"
+# This file is automatically @generated by Cargo.
+# It is not intended for manual editing.
+version = 3
+
+[[package]]
+name = "My-Crate"
+version = "1.2.3"
+"#,
+        )
+        .build();
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+    let output = std::process::Command::new(env!("CARGO"))
+        .args(["build", "--offline", "--locked"])
+        .current_dir(cook_directory.path())
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // Act
+    skeleton
+        .remove_compiled_dummies(
+            cook_directory.path(),
+            OptimisationProfile::Debug,
+            None,
+            None,
+        )
+        .unwrap();
+
+    // Assert
+    let lock_file = skeleton.lock_file.as_ref().unwrap();
+    assert!(lock_file.contains("name = \"My-Crate\"\nversion = \"0.0.1\""));
+    let leftovers = skeleton
+        .find_compiled_dummies(
+            cook_directory.path(),
+            OptimisationProfile::Debug,
+            None,
+            None,
+        )
+        .unwrap();
+    assert!(leftovers.is_empty(), "{:?}", leftovers);
+    let build_scripts: Vec<_> = globwalk::glob(format!(
+        "{}/target/debug/build/My-Crate-*/build[-_]script[-_]build*",
+        cook_directory.path().display()
+    ))
+    .unwrap()
+    .collect();
+    assert!(build_scripts.is_empty(), "{:?}", build_scripts);
+}
+
+#[test]
+pub fn bin_edition_override() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .bin_package(
+            ".",
+            r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2021"
+
+[[bin]]
+name = "legacy"
+path = "src/bin/legacy.rs"
+edition = "2015"
+"#,
+        )
+        .touch("src/bin/legacy.rs")
+        .touch("Cargo.lock")
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+
+    // Assert
+    check(
+        &skeleton.manifests[0].contents,
+        expect![[r#"
+            bench = []
+            test = []
+            example = []
+
+            [[bin]]
+            path = "src/bin/legacy.rs"
+            name = "legacy"
+            plugin = false
+            proc-macro = false
+            edition = "2015"
+            required-features = []
+
+            [package]
+            name = "test-dummy"
+            edition = "2021"
+            version = "0.0.1"
+        "#]],
+    );
+    let status = std::process::Command::new(env!("CARGO"))
+        .args(["check", "--offline", "--bin", "legacy"])
+        .current_dir(cook_directory.path())
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+#[test]
+pub fn workspace_inherited_version_lock() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[workspace]
+members = ["project-a", "project_b"]
+
+[workspace.package]
+version = "1.2.3"
+"#,
+        )
+        .bin_package(
+            "project-a",
+            r#"
+[package]
+name = "project-a"
+version.workspace = true
+edition = "2018"
+"#,
+        )
+        .lib_package(
+            "project_b",
+            r#"
+[package]
+name = "project_b"
+version = { workspace = true }
+edition = "2018"
+
+[dependencies]
+project-a = { path = "../project-a" }
+"#,
+        )
+        .file(
+            "Cargo.lock",
+            r#"
+# This file is automatically @generated by Cargo.
+# It is not intended for manual editing.
+version = 3
+
+[[package]]
+name = "project-a"
+version = "1.2.3"
+
+[[package]]
+name = "project_b"
+version = "1.2.3"
+dependencies = [
+ "project-a",
+]
+"#,
+        )
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+
+    // Assert
+    check(
+        &skeleton.lock_file.expect("there should be a lock_file"),
+        expect![[r#"
+            version = 3
+
+            [[package]]
+            name = "project-a"
+            version = "0.0.1"
+
+            [[package]]
+            name = "project_b"
+            version = "0.0.1"
+            dependencies = ["project-a"]
+        "#]],
+    );
+    check(
+        &skeleton.manifests[0].contents,
+        expect![[r#"
+            [workspace]
+            members = ["project-a", "project_b"]
+
+            [workspace.package]
+            version = "0.0.1"
+        "#]],
+    );
+}
+
+#[test]
+pub fn registry_packages_missing_from_the_cache() {
+    // Arrange
+    let lock_file = r#"
+version = 3
+
+[[package]]
+name = "either"
+version = "1.8.1"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "uuid"
+version = "0.8.0"
+source = "sparse+https://index.crates.io/"
+
+[[package]]
+name = "anyhow"
+version = "1.0.66"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "forked"
+version = "0.1.0"
+source = "git+https://github.com/example/forked#0123456789abcdef"
+
+[[package]]
+name = "local"
+version = "0.0.1"
+"#;
+    let cargo_home = TempDir::new().unwrap();
+    cargo_home
+        .child("registry/cache/index.crates.io-6f17d22bba15001f/either-1.8.1.crate")
+        .touch()
+        .unwrap();
+    cargo_home
+        .child("registry/cache/index.crates.io-6f17d22bba15001f/anyhow-1.0.65.crate")
+        .touch()
+        .unwrap();
+
+    // Act
+    let missing = missing_from_registry_cache(lock_file, cargo_home.path()).unwrap();
+
+    // Assert
+    assert_eq!(
+        missing,
+        vec![
+            RegistryPackage {
+                name: "uuid".to_string(),
+                version: "0.8.0".to_string(),
+            },
+            RegistryPackage {
+                name: "anyhow".to_string(),
+                version: "1.0.66".to_string(),
+            },
+        ]
+    );
+
+    // An empty `CARGO_HOME`
+    let empty_cargo_home = TempDir::new().unwrap();
+    let missing = missing_from_registry_cache(lock_file, empty_cargo_home.path()).unwrap();
+    assert_eq!(missing.len(), 3);
+}
+
+#[test]
+pub fn tool_config_files_are_captured() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .lib_package(
+            ".",
+            r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2021"
+        "#,
+        )
+        .file("clippy.toml", "msrv = \"1.70\"\n")
+        .file("deny.toml", "[licenses]\n")
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+
+    // Assert
+    let captured: Vec<_> = skeleton
+        .extra_files
+        .iter()
+        .map(|file| file.relative_path.clone())
+        .collect();
+    assert_eq!(
+        captured,
+        vec![PathBuf::from("clippy.toml"), PathBuf::from("deny.toml")]
+    );
+    cook_directory
+        .child("clippy.toml")
+        .assert("msrv = \"1.70\"\n");
+    cook_directory.child("deny.toml").assert("[licenses]\n");
+
+    // A custom list replaces the default one
+    let skeleton = Skeleton::derive_with_args(
+        project.path(),
+        &PrepareArgs {
+            tool_config_files: Some(vec!["deny.toml".to_string()]),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(skeleton.extra_files.len(), 1);
+    assert_eq!(
+        skeleton.extra_files[0].relative_path,
+        PathBuf::from("deny.toml")
+    );
+    assert!(DEFAULT_TOOL_CONFIG_FILES.contains(&"clippy.toml"));
+}
+
+#[test]
+pub fn specify_member_in_workspace_with_implicit_members() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .bin_package(
+            ".",
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+backend = { path = "backend" }
+ci = { path = "ci" }
+
+[workspace]
+
+[workspace.package]
+version = "0.1.0"
+edition = "2021"
+    "#,
+        )
+        .bin_package(
+            "backend",
+            r#"
+[package]
+name = "backend"
+version.workspace = true
+edition.workspace = true
+    "#,
+        )
+        .bin_package(
+            "ci",
+            r#"
+[package]
+name = "ci"
+version.workspace = true
+edition.workspace = true
+    "#,
+        )
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), "backend".to_string().into()).unwrap();
+
+    // Assert
+    let root_manifest = skeleton
+        .manifests
+        .iter()
+        .find(|manifest| manifest.relative_path == Path::new("Cargo.toml"))
+        .unwrap();
+    let root_manifest: toml::Value = toml::from_str(&root_manifest.contents).unwrap();
+    assert_eq!(
+        root_manifest["workspace"]["members"],
+        toml::Value::Array(vec![toml::Value::String("backend".to_string())])
+    );
+}
+
+#[test]
+pub fn cleanup_in_a_directory_with_spaces_and_non_ascii_characters() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .lib_package(
+            ".",
+            r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2018"
+build = "build.rs"
+"#,
+        )
+        .touch("build.rs")
+        .build();
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    let temp_directory = TempDir::new().unwrap();
+    let cook_directory = temp_directory.child("用户/my app");
+    cook_directory.create_dir_all().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+    let build_script =
+        cook_directory.child("target/debug/build/test-dummy-0123456789abcdef/build-script-build");
+    let library = cook_directory.child("target/debug/deps/libtest_dummy-0123456789abcdef.rlib");
+    let dependency = cook_directory.child("target/debug/deps/libuuid-0123456789abcdef.rlib");
+    for artifact in [&build_script, &library, &dependency] {
+        artifact.touch().unwrap();
+    }
+
+    // Act
+    skeleton
+        .remove_compiled_dummies(
+            cook_directory.path(),
+            OptimisationProfile::Debug,
+            None,
+            None,
+        )
+        .unwrap();
+
+    // Assert
+    cook_directory
+        .child("src/lib.rs")
+        .assert(predicate::path::exists());
+    build_script.assert(predicate::path::missing());
+    library.assert(predicate::path::missing());
+    dependency.assert(predicate::path::exists());
+}
+
+#[test]
+pub fn default_run_survives_the_selection_of_a_package() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[workspace]
+members = ["app", "backend"]
+"#,
+        )
+        .bin_package(
+            "app",
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2021"
+default-run = "admin"
+
+[[bin]]
+name = "admin"
+path = "src/bin/admin.rs"
+"#,
+        )
+        .touch("app/src/bin/admin.rs")
+        .bin_package(
+            "backend",
+            r#"
+[package]
+name = "backend"
+version = "0.1.0"
+edition = "2021"
+"#,
+        )
+        .build();
+    let default_run = |skeleton: &Skeleton| {
+        let manifest = skeleton
+            .manifests
+            .iter()
+            .find(|manifest| manifest.relative_path == Path::new("app/Cargo.toml"))
+            .unwrap();
+        let contents: toml::Value = toml::from_str(&manifest.contents).unwrap();
+        contents["package"]
+            .get("default-run")
+            .map(|default_run| default_run.as_str().unwrap().to_owned())
+    };
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    let app = Skeleton::derive(project.path(), "app".to_string().into()).unwrap();
+    let backend = Skeleton::derive(project.path(), "backend".to_string().into()).unwrap();
+
+    // Assert
+    // `--bin` selects a package: its binaries, hence `default-run`, are left untouched.
+    assert_eq!(default_run(&skeleton), Some("admin".to_string()));
+    assert_eq!(default_run(&app), Some("admin".to_string()));
+    assert_eq!(default_run(&backend), Some("admin".to_string()));
+}
+
+#[test]
+pub fn lock_files_of_nested_projects_are_captured() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[workspace]
+members = ["app"]
+exclude = ["tools"]
+"#,
+        )
+        .file(
+            "Cargo.lock",
+            r#"version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+"#,
+        )
+        .bin_package(
+            "app",
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2021"
+"#,
+        )
+        .bin_package(
+            "tools",
+            r#"
+[package]
+name = "tools"
+version = "0.1.0"
+edition = "2021"
+"#,
+        )
+        .file(
+            "tools/Cargo.lock",
+            r#"version = 3
+
+[[package]]
+name = "tools"
+version = "0.1.0"
+"#,
+        )
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive_with_args(
+        project.path(),
+        &PrepareArgs {
+            nested_projects: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+
+    // Assert
+    let manifests: Vec<_> = skeleton
+        .manifests
+        .iter()
+        .map(|manifest| manifest.relative_path.clone())
+        .collect();
+    assert_eq!(
+        manifests,
+        vec![
+            PathBuf::from("Cargo.toml"),
+            PathBuf::from("app/Cargo.toml"),
+            PathBuf::from("tools/Cargo.toml"),
+        ]
+    );
+    for (lock_file, package) in [("Cargo.lock", "app"), ("tools/Cargo.lock", "tools")] {
+        cook_directory
+            .child(lock_file)
+            .assert(predicate::str::contains(format!("name = \"{}\"", package)));
+    }
+    cook_directory
+        .child("tools/src/main.rs")
+        .assert(predicate::path::exists());
+
+    // Nested projects are ignored by default
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    assert_eq!(skeleton.manifests.len(), 2);
+    assert!(skeleton.extra_files.is_empty());
+}
+
+#[test]
+pub fn enable_features_of_a_package() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[workspace]
+members = ["project_a", "project_b"]
+"#,
+        )
+        .lib_package(
+            "project_a",
+            r#"
+[package]
+name = "project_a"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+project_b = { path = "../project_b", optional = true }
+
+[features]
+extra = ["dep:project_b"]
+"#,
+        )
+        .lib_package(
+            "project_b",
+            r#"
+[package]
+name = "project_b"
+version = "0.1.0"
+edition = "2021"
+"#,
+        )
+        .build();
+    let mut skeleton = Skeleton::derive(project.path(), None).unwrap();
+
+    // Act
+    skeleton
+        .enable_features("project_a", &["extra".to_string()])
+        .unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+
+    // Assert
+    cook_directory
+        .child("project_a/Cargo.toml")
+        .assert(predicate::str::contains(r#"default = ["extra"]"#));
+    let metadata = cargo_metadata::MetadataCommand::new()
+        .current_dir(cook_directory.path())
+        .exec()
+        .unwrap();
+    let resolve = metadata.resolve.unwrap();
+    let project_a = resolve
+        .nodes
+        .iter()
+        .find(|node| node.id.repr.contains("project_a"))
+        .unwrap();
+    assert!(project_a
+        .dependencies
+        .iter()
+        .any(|dependency| dependency.repr.contains("project_b")));
+    assert!(skeleton
+        .enable_features("project_a", &["missing".to_string()])
+        .is_err());
+    assert!(skeleton
+        .enable_features("project_c", &["extra".to_string()])
+        .is_err());
+}
+
+#[test]
+pub fn enable_features_across_the_workspace() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[workspace]
+members = ["project_a", "project_b", "project_c"]
+"#,
+        )
+        .lib_package(
+            "project_a",
+            r#"
+[package]
+name = "project_a"
+version = "0.1.0"
+edition = "2021"
+
+[features]
+default = ["std"]
+std = []
+tracing = []
+"#,
+        )
+        .lib_package(
+            "project_b",
+            r#"
+[package]
+name = "project_b"
+version = "0.1.0"
+edition = "2021"
+
+[features]
+tracing = []
+"#,
+        )
+        .lib_package(
+            "project_c",
+            r#"
+[package]
+name = "project_c"
+version = "0.1.0"
+edition = "2021"
+"#,
+        )
+        .build();
+    let mut skeleton = Skeleton::derive(project.path(), None).unwrap();
+
+    // Act
+    skeleton
+        .enable_workspace_features(&["tracing".to_string()])
+        .unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+
+    // Assert
+    cook_directory
+        .child("project_a/Cargo.toml")
+        .assert(predicate::str::contains(r#"default = ["std", "tracing"]"#));
+    cook_directory
+        .child("project_b/Cargo.toml")
+        .assert(predicate::str::contains(r#"default = ["tracing"]"#));
+    cook_directory
+        .child("project_c/Cargo.toml")
+        .assert(predicate::str::contains("[features]").not());
+    assert!(skeleton
+        .enable_workspace_features(&["missing".to_string()])
+        .is_err());
+}
+
+#[cfg(unix)]
+#[test]
+pub fn symlinked_manifests_are_restored_as_regular_files() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[workspace]
+members = ["project_a"]
+"#,
+        )
+        .file(
+            "templates/library.toml",
+            r#"
+[package]
+name = "project_a"
+version = "0.1.0"
+edition = "2021"
+"#,
+        )
+        .touch("project_a/src/lib.rs")
+        .build();
+    std::os::unix::fs::symlink(
+        "../templates/library.toml",
+        project.path().join("project_a/Cargo.toml"),
+    )
+    .unwrap();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+
+    // Assert
+    let manifest = cook_directory.child("project_a/Cargo.toml");
+    let metadata = std::fs::symlink_metadata(manifest.path()).unwrap();
+    assert!(metadata.file_type().is_file());
+    manifest.assert(predicate::str::contains(r#"name = "project_a""#));
+    cook_directory
+        .child("templates")
+        .assert(predicate::path::missing());
+}
+
+#[test]
+pub fn look_up_the_manifest_of_a_package() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[workspace]
+members = ["crates/*"]
+"#,
+        )
+        .bin_package(
+            "crates/project_a",
+            r#"
+[package]
+name = "project_a"
+version = "0.1.0"
+edition = "2021"
+"#,
+        )
+        .lib_package(
+            "crates/project_b",
+            r#"
+[package]
+name = "project-b"
+version = "0.1.0"
+edition = "2021"
+"#,
+        )
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+
+    // Assert
+    let manifest = skeleton.manifest_for("project-b").unwrap();
+    assert_eq!(
+        manifest.relative_path,
+        PathBuf::from("crates/project_b/Cargo.toml")
+    );
+    assert_eq!(
+        manifest.targets[0].kind(),
+        &TargetKind::Lib {
+            is_proc_macro: false
+        }
+    );
+    assert!(skeleton.manifest_for("project_b").is_none());
+    assert!(skeleton.manifest_for("project_c").is_none());
+}
+
+#[test]
+pub fn cleanup_wasm_artifacts() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .lib_package(
+            ".",
+            r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2021"
+
+[lib]
+crate-type = ["cdylib", "rlib"]
+"#,
+        )
+        .build();
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+    let profile_directory = cook_directory.child("target/wasm32-unknown-unknown/debug");
+    let dummies = [
+        profile_directory.child("deps/libtest_dummy-0123456789abcdef.rlib"),
+        profile_directory.child("deps/test_dummy.wasm"),
+        profile_directory.child("test_dummy.wasm"),
+    ];
+    let dependency = profile_directory.child("deps/libuuid-0123456789abcdef.rlib");
+    for artifact in dummies.iter().chain([&dependency]) {
+        artifact.touch().unwrap();
+    }
+
+    // Act
+    skeleton
+        .remove_compiled_dummies(
+            cook_directory.path(),
+            OptimisationProfile::Debug,
+            Some(vec!["wasm32-unknown-unknown".to_string()]),
+            None,
+        )
+        .unwrap();
+
+    // Assert
+    for dummy in &dummies {
+        dummy.assert(predicate::path::missing());
+    }
+    dependency.assert(predicate::path::exists());
+}
+
+#[test]
+pub fn remote_dependencies_named_after_a_local_crate_are_not_masked() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[workspace]
+members = ["uuid", "project_a", "project_b"]
+"#,
+        )
+        .lib_package(
+            "uuid",
+            r#"
+[package]
+name = "uuid"
+version = "0.3.0"
+edition = "2021"
+publish = false
+"#,
+        )
+        .lib_package(
+            "project_a",
+            r#"
+[package]
+name = "project_a"
+version = "0.1.0"
+edition = "2021"
+publish = false
+
+[dependencies]
+uuid = { path = "../uuid", version = "0.3.0" }
+"#,
+        )
+        .lib_package(
+            "project_b",
+            r#"
+[package]
+name = "project_b"
+version = "0.1.0"
+edition = "2021"
+publish = false
+
+[dependencies]
+uuid = { version = "1.0.0", default-features = false }
+
+[build-dependencies]
+uuid = "1.0.0"
+"#,
+        )
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+
+    // Assert
+    let dependencies = |package: &str| {
+        let manifest = skeleton.manifest_for(package).unwrap();
+        let contents: toml::Value = toml::from_str(&manifest.contents).unwrap();
+        (
+            contents["dependencies"]["uuid"].clone(),
+            contents.get("build-dependencies").cloned(),
+        )
+    };
+    let (local, _) = dependencies("project_a");
+    assert_eq!(local["version"].as_str(), Some("0.0.1"));
+    let (remote, remote_build) = dependencies("project_b");
+    assert_eq!(remote["version"].as_str(), Some("1.0.0"));
+    assert_eq!(remote_build.unwrap()["uuid"].as_str(), Some("1.0.0"));
+}
+
+#[test]
+pub fn specify_member_listed_with_a_non_normalized_path() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[workspace]
+members = ["./crates/foo/", "crates/bar"]
+"#,
+        )
+        .bin_package(
+            "crates/foo",
+            r#"
+[package]
+name = "foo"
+version = "0.1.0"
+edition = "2021"
+"#,
+        )
+        .bin_package(
+            "crates/bar",
+            r#"
+[package]
+name = "bar"
+version = "0.1.0"
+edition = "2021"
+"#,
+        )
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), "foo".to_string().into()).unwrap();
+
+    // Assert
+    let root_manifest = skeleton
+        .manifests
+        .iter()
+        .find(|manifest| manifest.relative_path == Path::new("Cargo.toml"))
+        .unwrap();
+    assert_eq!(
+        root_manifest.contents,
+        "[workspace]\nmembers = [\"crates/foo\"]\n"
+    );
+}
+
+#[test]
+pub fn harness_detection_for_auto_discovered_benches_and_tests() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .lib_package(
+            ".",
+            r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2021"
+
+# The paths of these targets are auto-discovered.
+[[bench]]
+name = "no_harness"
+harness = false
+
+[[bench]]
+name = "directory"
+harness = false
+
+[[test]]
+name = "custom"
+harness = false
+"#,
+        )
+        .touch("benches/no_harness.rs")
+        .touch("benches/directory/main.rs")
+        .touch("benches/with_harness.rs")
+        .touch("tests/custom.rs")
+        .touch("tests/with_harness.rs")
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+
+    // Assert
+    for (path, content) in [
+        ("benches/no_harness.rs", "fn main() {}"),
+        ("benches/directory/main.rs", "fn main() {}"),
+        ("benches/with_harness.rs", ""),
+        ("tests/custom.rs", "fn main() {}"),
+        ("tests/with_harness.rs", ""),
+    ] {
+        cook_directory.child(path).assert(content);
+    }
+}
+
+#[test]
+pub fn git_sourced_packages_named_after_a_local_crate_are_not_masked() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .bin_package(
+            ".",
+            r#"
+[package]
+name = "test-dummy"
+version = "1.2.3"
+edition = "2021"
+"#,
+        )
+        .file(
+            "Cargo.lock",
+            r#"
+# This file is automatically @generated by Cargo.
+# It is not intended for manual editing.
+version = 3
+
+[[package]]
+name = "test-dummy"
+version = "1.2.0"
+source = "git+https://github.com/fork/test-dummy?branch=testing#0123456789abcdef0123456789abcdef01234567"
+
+[[package]]
+name = "test-dummy"
+version = "1.2.3"
+"#,
+        )
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+
+    // Assert
+    let lock_file: toml::Value = toml::from_str(&skeleton.lock_file.unwrap()).unwrap();
+    let packages: Vec<_> = lock_file["package"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|package| {
+            (
+                package["version"].as_str().unwrap(),
+                package.get("source").is_some(),
+            )
+        })
+        .collect();
+    assert_eq!(packages, vec![("1.2.0", true), ("0.0.1", false)]);
+}
+
+#[test]
+pub fn features_tables_are_round_tripped_faithfully() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[workspace]
+members = ["no_features", "empty_default"]
+"#,
+        )
+        .lib_package(
+            "no_features",
+            r#"
+[package]
+name = "no_features"
+version = "0.1.0"
+edition = "2021"
+"#,
+        )
+        .lib_package(
+            "empty_default",
+            r#"
+[package]
+name = "empty_default"
+version = "0.1.0"
+edition = "2021"
+
+[features]
+default = []
+"#,
+        )
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    let second_skeleton = Skeleton::derive(project.path(), None).unwrap();
+
+    // Assert
+    assert_eq!(skeleton, second_skeleton);
+    let no_features = &skeleton.manifest_for("no_features").unwrap().contents;
+    assert!(!no_features.contains("[features]"));
+    let empty_default = &skeleton.manifest_for("empty_default").unwrap().contents;
+    assert!(empty_default.contains("[features]\ndefault = []\n"));
+}
+
+#[test]
+pub fn exclude_bins() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .bin_package(
+            ".",
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2021"
+default-run = "migrate"
+"#,
+        )
+        .touch("src/bin/worker.rs")
+        .touch("src/bin/migrate.rs")
+        .build();
+    let prepare_args = |excluded_bins: &[&str]| PrepareArgs {
+        excluded_bins: excluded_bins.iter().map(|bin| bin.to_string()).collect(),
+        ..Default::default()
+    };
+
+    // Act
+    let skeleton = Skeleton::derive_with_args(project.path(), &prepare_args(&["migrate"])).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+
+    // Assert
+    let bins: Vec<_> = skeleton.manifests[0]
+        .targets
+        .iter()
+        .filter(|target| target.kind() == &TargetKind::Bin)
+        .map(|target| target.name())
+        .collect();
+    assert_eq!(bins, vec!["worker", "app"]);
+    assert!(!skeleton.manifests[0].contents.contains("migrate"));
+    cook_directory
+        .child("src/bin/migrate.rs")
+        .assert(predicate::path::missing());
+    cook_directory
+        .child("src/bin/worker.rs")
+        .assert("fn main() {}");
+    // `cargo` is happy with the pruned manifest
+    cargo_metadata::MetadataCommand::new()
+        .current_dir(cook_directory.path())
+        .no_deps()
+        .exec()
+        .unwrap();
+
+    assert!(Skeleton::derive_with_args(project.path(), &prepare_args(&["missing"])).is_err());
+}
+
+#[test]
+pub fn profile_package_overrides_are_preserved() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[workspace]
+members = ["backend"]
+
+[profile.release]
+lto = true
+
+[profile.release.package."*"]
+opt-level = 2
+
+[profile.release.package.sqlx]
+opt-level = 0
+debug = true
+
+[profile.release.build-override]
+opt-level = 3
+"#,
+        )
+        .bin_package(
+            "backend",
+            r#"
+[package]
+name = "backend"
+version = "0.1.0"
+edition = "2021"
+"#,
+        )
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+
+    // Assert
+    check(
+        &skeleton.manifests[0].contents,
+        expect![[r#"
+            [workspace]
+            members = ["backend"]
+
+            [profile.release]
+            lto = true
+
+            [profile.release.package."*"]
+            opt-level = 2
+
+            [profile.release.package.sqlx]
+            opt-level = 0
+            debug = true
+
+            [profile.release.build-override]
+            opt-level = 3
+        "#]],
+    );
+    cook_directory
+        .child("Cargo.toml")
+        .assert(skeleton.manifests[0].contents.as_str());
+}
+
+#[test]
+pub fn external_dependencies_are_resolved_against_the_lock_file() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[workspace]
+members = ["app", "utils"]
+
+[workspace.dependencies]
+json = { package = "serde_json", version = "1" }
+"#,
+        )
+        .bin_package(
+            "app",
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+serde = "1"
+rand = "0.8"
+json = { workspace = true }
+utils = { path = "../utils" }
+tokio = { version = "1", optional = true }
+
+[dev-dependencies]
+insta = "1"
+
+[build-dependencies]
+cc = "1"
+
+[target.'cfg(windows)'.dependencies]
+winapi = "0.3"
+"#,
+        )
+        .lib_package(
+            "utils",
+            r#"
+[package]
+name = "utils"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+rand = "0.7"
+"#,
+        )
+        .file(
+            "Cargo.lock",
+            r#"
+# This file is automatically @generated by Cargo.
+# It is not intended for manual editing.
+version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = [
+ "cc",
+ "insta",
+ "rand 0.8.5",
+ "serde",
+ "serde_json",
+ "tokio",
+ "utils",
+ "winapi",
+]
+
+[[package]]
+name = "cc"
+version = "1.0.83"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "insta"
+version = "1.34.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "rand"
+version = "0.7.3"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "rand"
+version = "0.8.5"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "serde"
+version = "1.0.196"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "serde_json"
+version = "1.0.113"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "tokio"
+version = "1.36.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "utils"
+version = "0.1.0"
+dependencies = [
+ "rand 0.7.3",
+]
+
+[[package]]
+name = "winapi"
+version = "0.3.9"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#,
+        )
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+
+    // Assert
+    assert_eq!(
+        skeleton.external_dependencies().unwrap(),
+        vec![
+            "rand@0.7.3",
+            "rand@0.8.5",
+            "serde@1.0.196",
+            "serde_json@1.0.113"
+        ]
+    );
+}
+
+#[test]
+pub fn library_outside_of_src() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2021"
+
+[lib]
+path = "source/lib.rs"
+"#,
+        )
+        .touch("source/lib.rs")
+        .build();
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+
+    // Act
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+    let profile_directory = cook_directory.child("target/debug");
+    let dummies = [
+        profile_directory.child("deps/libtest_dummy-0123456789abcdef.rlib"),
+        profile_directory.child("deps/libtest_dummy-0123456789abcdef.rmeta"),
+        profile_directory.child("libtest_dummy.rlib"),
+    ];
+    for dummy in &dummies {
+        dummy.touch().unwrap();
+    }
+    skeleton
+        .remove_compiled_dummies(
+            cook_directory.path(),
+            OptimisationProfile::Debug,
+            None,
+            None,
+        )
+        .unwrap();
+
+    // Assert
+    cook_directory.child("source").child("lib.rs").assert("");
+    cook_directory
+        .child("src")
+        .assert(predicate::path::missing());
+    for dummy in &dummies {
+        dummy.assert(predicate::path::missing());
+    }
+}
+
+#[test]
+pub fn members_used_as_path_dependencies_are_listed_once() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .bin_package(
+            ".",
+            r#"
+[package]
+name = "root"
+version = "0.1.0"
+edition = "2021"
+
+[workspace]
+members = [".", "a", "b"]
+
+[dependencies]
+a = { path = "a" }
+b = { path = "./b" }
+"#,
+        )
+        .lib_package(
+            "a",
+            r#"
+[package]
+name = "a"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+b = { path = "../b" }
+"#,
+        )
+        .lib_package(
+            "b",
+            r#"
+[package]
+name = "b"
+version = "0.1.0"
+edition = "2021"
+"#,
+        )
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+
+    // Assert
+    let relative_paths: Vec<_> = skeleton
+        .manifests
+        .iter()
+        .map(|manifest| manifest.relative_path.clone())
+        .collect();
+    assert_eq!(
+        relative_paths,
+        vec![
+            PathBuf::from("Cargo.toml"),
+            PathBuf::from("a/Cargo.toml"),
+            PathBuf::from("b/Cargo.toml"),
+        ]
+    );
+}
+
+#[test]
+pub fn auto_discovery_can_be_disabled() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            ".",
+            r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2024"
+autobins = false
+autolib = false
+
+[[bin]]
+name = "app"
+path = "src/app.rs"
+edition = "2024"
+"#,
+        )
+        .touch("src/app.rs")
+        .touch("src/lib.rs")
+        .touch("src/main.rs")
+        .touch("src/bin/stray.rs")
+        .build();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), None).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+
+    // Assert
+    let manifest = &skeleton.manifests[0];
+    let targets: Vec<_> = manifest
+        .targets
+        .iter()
+        .map(|target| (target.kind().clone(), target.name().to_owned()))
+        .collect();
+    assert_eq!(targets, vec![(TargetKind::Bin, "app".to_string())]);
+    let src = cook_directory.child("src");
+    src.child("app.rs").assert("fn main() {}");
+    for stray in ["lib.rs", "main.rs", "bin"] {
+        src.child(stray).assert(predicate::path::missing());
+    }
+    check(
+        &manifest.contents,
+        expect![[r#"
+        bench = []
+        test = []
+        example = []
+
+        [[bin]]
+        path = "src/app.rs"
+        name = "app"
+        plugin = false
+        proc-macro = false
+        required-features = []
+        edition = "2024"
+
+        [package]
+        name = "test-dummy"
+        version = "0.0.1"
+        autobins = false
+        edition = "2024"
+        autolib = false
+    "#]],
+    );
+}
+
+#[test]
+pub fn workspace_root_can_be_overridden() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            "rust",
+            r#"
+[workspace]
+members = ["app"]
+"#,
+        )
+        .bin_package(
+            "rust/app",
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2021"
+"#,
+        )
+        .build();
+    let root = project.path().canonicalize().unwrap();
+
+    // Act
+    let skeleton = Skeleton::derive_with_args(
+        root.join("rust"),
+        &PrepareArgs {
+            workspace_root: Some(root.clone()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+
+    // Assert
+    let relative_paths: Vec<_> = skeleton
+        .manifests
+        .iter()
+        .map(|manifest| manifest.relative_path.clone())
+        .collect();
+    assert_eq!(
+        relative_paths,
+        vec![
+            PathBuf::from("rust/Cargo.toml"),
+            PathBuf::from("rust/app/Cargo.toml"),
+        ]
+    );
+    cook_directory
+        .child("rust/app/src/main.rs")
+        .assert("fn main() {}");
+
+    // All manifests must be inside the root
+    let error = Skeleton::derive_with_args(
+        root.join("rust"),
+        &PrepareArgs {
+            workspace_root: Some(root.join("rust/app")),
+            ..Default::default()
+        },
+    )
+    .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("is not inside the root of the skeleton"));
+}
+
+#[test]
+pub fn member_selection_works_with_an_overridden_workspace_root() {
+    // Arrange
+    let project = CargoWorkspace::new()
+        .manifest(
+            "ws",
+            r#"
+[workspace]
+members = ["a", "b"]
+default-members = ["b"]
+"#,
+        )
+        .bin_package(
+            "ws/a",
+            r#"
+[package]
+name = "a"
+version = "0.1.0"
+edition = "2021"
+"#,
+        )
+        .bin_package(
+            "ws/b",
+            r#"
+[package]
+name = "b"
+version = "0.1.0"
+edition = "2021"
+"#,
+        )
+        .build();
+    let root = project.path().canonicalize().unwrap();
+
+    // Act
+    let skeleton = Skeleton::derive_with_args(
+        root.join("ws/a"),
+        &PrepareArgs {
+            member: Some("a".to_string()),
+            workspace_root: Some(root.clone()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    // Assert
+    let workspace_manifest = skeleton
+        .manifests
+        .iter()
+        .find(|manifest| manifest.relative_path == Path::new("ws/Cargo.toml"))
+        .unwrap();
+    check(
+        &workspace_manifest.contents,
+        expect![[r#"
+            [workspace]
+            members = ["a"]
+        "#]],
+    );
+    let skeleton = Skeleton::derive_with_args(
+        root.join("ws"),
+        &PrepareArgs {
+            workspace_root: Some(root.clone()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(skeleton.default_member_count().unwrap(), 1);
+}
+
+#[test]
+pub fn nested_projects_scan_skips_pruned_directories() {
+    // Arrange
+    let lock_file = |package: &str| {
+        format!(
+            "version = 3\n\n[[package]]\nname = \"{}\"\nversion = \"0.1.0\"\n",
+            package
+        )
+    };
+    let mut project = CargoWorkspace::new();
+    project
+        .manifest(
+            ".",
+            r#"
+[workspace]
+members = ["app"]
+exclude = ["tools", "target", ".git", "web"]
+"#,
+        )
+        .bin_package(
+            "app",
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2021"
+"#,
+        )
+        .bin_package(
+            "tools/a/b/c/d/cli",
+            r#"
+[package]
+name = "cli"
+version = "0.1.0"
+edition = "2021"
+"#,
+        )
+        .file("tools/a/b/c/d/cli/Cargo.lock", &lock_file("cli"));
+    // Fully-fledged projects, that would be captured if these directories were scanned.
+    for (directory, package) in [
+        ("target/package/vendored-0.1.0", "vendored"),
+        (".git/modules/sub", "sub"),
+        ("web/node_modules/wasm-pkg", "wasm-pkg"),
+    ] {
+        project
+            .lib_package(
+                directory,
+                &format!(
+                    "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+                    package
+                ),
+            )
+            .file(format!("{}/Cargo.lock", directory), &lock_file(package));
+    }
+    let project = project.build();
+
+    // Act
+    let skeleton = Skeleton::derive_with_args(
+        project.path(),
+        &PrepareArgs {
+            nested_projects: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    // Assert
+    let extra_files: Vec<_> = skeleton
+        .extra_files
+        .iter()
+        .map(|file| file.relative_path.clone())
+        .collect();
+    assert_eq!(
+        extra_files,
+        vec![PathBuf::from("tools/a/b/c/d/cli/Cargo.lock")]
+    );
 }